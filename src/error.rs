@@ -0,0 +1,108 @@
+use std::fmt;
+use std::io;
+use std::str::Utf8Error;
+
+use crate::fat16::FatType;
+
+/// Errors que poden retornar les operacions sobre un volum.
+#[derive(Debug)]
+pub enum ShooterError {
+    /// No s'ha pogut llegir el fitxer del volum.
+    VolumeNotFound(io::Error),
+    /// El volum no està formatat en FAT ni Ext2, o la seva geometria no té sentit.
+    UnrecognizedFormat,
+    /// El volum és FAT però d'una versió no suportada.
+    UnsupportedFat(FatType),
+    /// Un nom emmagatzemat al volum no és UTF-8 vàlid.
+    InvalidName(Utf8Error),
+    /// El volum és més petit del que indiquen les seves estructures.
+    TruncatedImage { expected: usize, found: usize },
+    /// Una estructura apunta fora del volum.
+    OutOfBounds { offset: usize, len: usize },
+    /// El fitxer demanat no existeix dins del volum.
+    FileNotFound,
+    /// L'operació necessita un fitxer però el path és un directori.
+    IsADirectory,
+    /// L'operació necessita un directori però el path és un fitxer.
+    NotADirectory,
+    /// No es pot eliminar un directori que no és buit.
+    DirectoryNotEmpty,
+    /// L'operació no es pot fer sobre el root directory.
+    RootDirectory,
+    /// No s'ha pogut escriure el volum modificat o el fitxer extret.
+    WriteFailed(io::Error),
+    /// El volum s'ha llegit de l'entrada estàndard i no es pot modificar.
+    ReadOnlyVolume,
+    /// El volum fa servir funcionalitats (pel nom) que no sabem mantenir en modificar-lo.
+    UnsupportedFeatures(Vec<String>),
+    /// Les dades d'un fitxer esborrat ja no hi són (clusters reutilitzats o no conservats).
+    NotRecoverable,
+    /// Ja hi ha una entrada amb aquest nom al directori.
+    AlreadyExists,
+    /// Aquest tipus de volum no implementa l'operació.
+    Unsupported,
+    /// No queda espai lliure (clusters, blocs, inodes o entrades de directori).
+    NoSpace,
+    /// El nom no cap en una entrada de directori.
+    NameTooLong,
+    /// No s'ha pogut llegir el fitxer del host que es vol copiar al volum.
+    ReadFailed(io::Error),
+    /// No es pot moure un directori a dins de si mateix o d'un dels seus subdirectoris.
+    MoveIntoItself,
+    /// Resoldre el path passa per massa enllaços simbòlics, probablement un cicle.
+    SymlinkLoop,
+    /// L'operació necessita un fitxer però el path és un enllaç simbòlic.
+    IsASymlink,
+    /// L'operació necessita un enllaç simbòlic però el path no ho és.
+    NotASymlink,
+    /// L'arbre de directoris o una cadena de clusters torna a passar per un lloc on ja havia estat.
+    DirectoryCycle,
+}
+
+impl fmt::Display for ShooterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShooterError::VolumeNotFound(e) => write!(f, "volume not found: {}", e),
+            ShooterError::UnrecognizedFormat => write!(f, "volume is not formatted as FAT nor Ext2"),
+            ShooterError::UnsupportedFat(t) => write!(f, "unsupported {} filesystem version", t),
+            ShooterError::InvalidName(e) => write!(f, "invalid name: {}", e),
+            ShooterError::TruncatedImage { expected, found } => write!(f, "truncated image: expected {} bytes, found {}", expected, found),
+            ShooterError::OutOfBounds { offset, len } => write!(f, "structure out of bounds: {} bytes at offset {}", len, offset),
+            ShooterError::FileNotFound => write!(f, "file not found"),
+            ShooterError::IsADirectory => write!(f, "path is a directory"),
+            ShooterError::NotADirectory => write!(f, "path is not a directory"),
+            ShooterError::DirectoryNotEmpty => write!(f, "directory not empty"),
+            ShooterError::RootDirectory => write!(f, "operation not allowed on the root directory"),
+            ShooterError::WriteFailed(e) => write!(f, "unable to save the filesystem: {}", e),
+            ShooterError::ReadOnlyVolume => write!(f, "volume read from stdin cannot be modified"),
+            ShooterError::UnsupportedFeatures(features) => write!(f, "refusing to modify a volume with unsupported features: {}", features.join(", ")),
+            ShooterError::NotRecoverable => write!(f, "deleted file data has been overwritten or was not preserved"),
+            ShooterError::AlreadyExists => write!(f, "an entry with that name already exists"),
+            ShooterError::Unsupported => write!(f, "operation not supported on this filesystem"),
+            ShooterError::NoSpace => write!(f, "no space left on the volume"),
+            ShooterError::NameTooLong => write!(f, "file name too long"),
+            ShooterError::ReadFailed(e) => write!(f, "unable to read the host file: {}", e),
+            ShooterError::MoveIntoItself => write!(f, "cannot move a directory inside itself"),
+            ShooterError::SymlinkLoop => write!(f, "too many levels of symbolic links"),
+            ShooterError::IsASymlink => write!(f, "path is a symbolic link"),
+            ShooterError::NotASymlink => write!(f, "path is not a symbolic link"),
+            ShooterError::DirectoryCycle => write!(f, "cycle in the directory tree or in a cluster chain"),
+        }
+    }
+}
+
+impl std::error::Error for ShooterError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ShooterError::VolumeNotFound(e) | ShooterError::WriteFailed(e) | ShooterError::ReadFailed(e) => Some(e),
+            ShooterError::InvalidName(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<Utf8Error> for ShooterError {
+    fn from(e: Utf8Error) -> Self {
+        ShooterError::InvalidName(e)
+    }
+}
@@ -1,21 +1,28 @@
-use std::cmp::min;
+use std::cmp::{max, min};
 
+use crate::error::ShooterError;
 use crate::generics::*;
 use crate::utils::*;
-use std::process::exit;
-use std::fs;
-use std::cell::{Cell, RefCell};
-use std::borrow::BorrowMut;
+use std::path::PathBuf;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::io::Write;
 
-pub(crate) struct Ext2 {
-    file_name: String,
+pub struct Ext2 {
     data: Vec<u8>,
-    vol_name: String,
+    path: Option<PathBuf>,
 
-    used_blocks: RefCell<Vec<u32>>,
+    // Blocs de dades de l'últim inode recorregut.
+    used_blocks: RefCell<Vec<DataBlock>>,
+    // Blocs indirectes i nodes de l'arbre d'extents de l'últim inode recorregut.
+    index_blocks: RefCell<Vec<u32>>,
 
+    // Taula de descriptors de grup, en ordre de grup.
+    group_descriptors: Vec<GroupDescriptor>,
+
+    // Punters que caben en un bloc indirecte.
     indirect_block_row_count: u32,
-    double_indirect_top_row: u32,
 
     inode_size: u16,
     inode_count: u32,
@@ -33,470 +40,1871 @@ pub(crate) struct Ext2 {
 
     volume_name: String,
 
+    // Funcionalitats del volum. s_feature_incompat ens diu, entre d'altres, si els inodes poden fer servir extents (ext4).
+    feature_compat: u32,
+    feature_incompat: u32,
+    feature_ro_compat: u32,
+
     last_check: u32,
     last_mount: u32,
     last_write: u32,
+
+    // Si és cert, delete conserva i_size i i_blocks per poder recuperar l'inode.
+    soft_delete: bool,
+    // Si és cert, lookup continua pel destí dels enllaços simbòlics.
+    follow_symlinks: bool,
+}
+
+/// Informació del superblock d'un volum Ext2.
+#[derive(Debug, Clone)]
+pub struct Ext2Info {
+    pub inode_size: u16,
+    pub inode_count: u32,
+    pub first_inode: u32,
+    pub inodes_per_group: u32,
+    pub free_inodes: u32,
+
+    pub block_size: u32,
+    pub reserved_blocks: u32,
+    pub free_blocks: u32,
+    pub block_count: u32,
+    pub first_block: u32,
+    pub blocks_per_group: u32,
+    pub frags_per_group: u32,
+
+    pub volume_name: String,
+    pub group_count: u32,
+    /// Màscares s_feature_compat, s_feature_incompat i s_feature_ro_compat del superblock.
+    pub feature_compat: u32,
+    pub feature_incompat: u32,
+    pub feature_ro_compat: u32,
+    /// Noms de totes les funcionalitats actives, p.ex. "filetype" o "has_journal".
+    pub features: Vec<String>,
+    /// Timestamps UNIX.
+    pub last_check: u32,
+    pub last_mount: u32,
+    pub last_write: u32,
+}
+
+// Noms de les funcionalitats de s_feature_compat, s_feature_incompat i s_feature_ro_compat.
+const FEATURES_COMPAT: [(u32, &str); 11] = [
+    (0x0001, "dir_prealloc"),
+    (0x0002, "imagic_inodes"),
+    (0x0004, "has_journal"),
+    (0x0008, "ext_attr"),
+    (0x0010, "resize_inode"),
+    (0x0020, "dir_index"),
+    (0x0040, "lazy_bg"),
+    (0x0200, "sparse_super2"),
+    (0x0400, "fast_commit"),
+    (0x0800, "stable_inodes"),
+    (0x1000, "orphan_file"),
+];
+
+const FEATURES_INCOMPAT: [(u32, &str); 16] = [
+    (0x0001, "compression"),
+    (0x0002, "filetype"),
+    (0x0004, "needs_recovery"),
+    (0x0008, "journal_dev"),
+    (0x0010, "meta_bg"),
+    (0x0040, "extent"),
+    (0x0080, "64bit"),
+    (0x0100, "mmp"),
+    (0x0200, "flex_bg"),
+    (0x0400, "ea_inode"),
+    (0x1000, "dirdata"),
+    (0x2000, "metadata_csum_seed"),
+    (0x4000, "large_dir"),
+    (0x8000, "inline_data"),
+    (0x10000, "encrypt"),
+    (0x20000, "casefold"),
+];
+
+const FEATURES_RO_COMPAT: [(u32, &str); 16] = [
+    (0x0001, "sparse_super"),
+    (0x0002, "large_file"),
+    (0x0004, "btree_dir"),
+    (0x0008, "huge_file"),
+    (0x0010, "uninit_bg"),
+    (0x0020, "dir_nlink"),
+    (0x0040, "extra_isize"),
+    (0x0100, "quota"),
+    (0x0200, "bigalloc"),
+    (0x0400, "metadata_csum"),
+    (0x0800, "replica"),
+    (0x1000, "read-only"),
+    (0x2000, "project"),
+    (0x4000, "shared_blocks"),
+    (0x8000, "verity"),
+    (0x10000, "orphan_present"),
+];
+
+// EXT2_FEATURE_INCOMPAT_FILETYPE: les entrades de directori guarden el tipus de l'inode.
+const FEATURE_INCOMPAT_FILETYPE: u32 = 0x0002;
+
+// EXT4_FEATURE_INCOMPAT_EXTENTS del superblock i EXT4_EXTENTS_FL de l'i_flags de l'inode.
+const FEATURE_INCOMPAT_EXTENTS: u32 = 0x0040;
+const EXTENTS_FL: u32 = 0x0008_0000;
+
+// EXT4_FEATURE_INCOMPAT_64BIT: els descriptors de grup fan s_desc_size bytes i porten la part alta dels camps.
+const FEATURE_INCOMPAT_64BIT: u32 = 0x0080;
+
+// EXT4_FEATURE_INCOMPAT_FLEX_BG: els bitmaps i la taula d'inodes d'un grup poden ser a un altre grup.
+const FEATURE_INCOMPAT_FLEX_BG: u32 = 0x0200;
+
+// EXT2_FEATURE_RO_COMPAT_SPARSE_SUPER i EXT4_FEATURE_COMPAT_SPARSE_SUPER2: quins grups tenen còpia del superblock.
+const FEATURE_RO_COMPAT_SPARSE_SUPER: u32 = 0x0001;
+const FEATURE_COMPAT_SPARSE_SUPER2: u32 = 0x0200;
+
+// EXT2_FEATURE_RO_COMPAT_LARGE_FILE: i_size_high guarda la part alta de la mida dels fitxers.
+const FEATURE_RO_COMPAT_LARGE_FILE: u32 = 0x0002;
+
+// EXT4_FEATURE_RO_COMPAT_HUGE_FILE: i_blocks té 48 bits (l_i_blocks_hi), i amb EXT4_HUGE_FILE_FL a l'i_flags
+// compta blocs del volum en lloc de sectors de 512 bytes.
+const FEATURE_RO_COMPAT_HUGE_FILE: u32 = 0x0008;
+const HUGE_FILE_FL: u32 = 0x0004_0000;
+
+// EXT4_FEATURE_RO_COMPAT_DIR_NLINK: un directori amb massa subdirectoris té i_links_count a 1.
+const FEATURE_RO_COMPAT_DIR_NLINK: u32 = 0x0020;
+
+// EXT4_FEATURE_RO_COMPAT_EXTRA_ISIZE: els inodes grans reserven s_want_extra_isize bytes de camps extra.
+const FEATURE_RO_COMPAT_EXTRA_ISIZE: u32 = 0x0040;
+
+// Funcionalitats incompat i ro_compat amb què sabem modificar el volum sense deixar-lo inconsistent.
+// Les compat no cal comprovar-les: per definició, qui no les entén pot escriure igualment.
+const SUPPORTED_INCOMPAT: u32 = FEATURE_INCOMPAT_FILETYPE | FEATURE_INCOMPAT_EXTENTS | FEATURE_INCOMPAT_FLEX_BG;
+const SUPPORTED_RO_COMPAT: u32 = FEATURE_RO_COMPAT_SPARSE_SUPER | FEATURE_RO_COMPAT_LARGE_FILE | FEATURE_RO_COMPAT_HUGE_FILE
+    | FEATURE_RO_COMPAT_DIR_NLINK | FEATURE_RO_COMPAT_EXTRA_ISIZE;
+
+// Retorna el nom de cada bit actiu de mask. Els bits que no coneixem es mostren en hexadecimal.
+fn feature_names(mask: u32, table: &[(u32, &str)]) -> Vec<String> {
+    (0..32)
+        .map(|bit| 1u32 << bit)
+        .filter(|flag| mask & flag != 0)
+        .map(|flag| match table.iter().find(|(f, _)| *f == flag) {
+            Some((_, name)) => name.to_string(),
+            None => format!("0x{:x}", flag),
+        })
+        .collect()
+}
+
+// EXT2_INDEX_FL: el directori té un índex hash (dir_index).
+const INDEX_FL: u32 = 0x0000_1000;
+
+// Magic number de la capçalera d'un node de l'arbre d'extents.
+const EXTENT_MAGIC: u16 = 0xF30A;
+// L'arbre d'extents no pot tenir més de 5 nivells.
+const EXTENT_MAX_DEPTH: u16 = 5;
+
+// Enllaços simbòlics que es poden seguir resolent un sol path, com el kernel (MAXSYMLINKS).
+const MAX_SYMLINKS: u32 = 40;
+
+// Màxim d'enllaços d'un inode (EXT4_LINK_MAX).
+const EXT2_LINK_MAX: u16 = 65000;
+
+// Camps d'un inode que fem servir.
+struct Inode {
+    mode: u16,
+    size: u32,
+    links_count: u16,
+    atime: u32,
+    mtime: u32,
+    dtime: u32,
+}
+
+impl Inode {
+    fn is_dir(&self) -> bool {
+        (self.mode & 0xF000) == 0x4000
+    }
+
+    // Valor del camp file_type de les entrades de directori per aquest tipus d'inode.
+    fn dir_entry_type(&self) -> u8 {
+        match self.mode & 0xF000 {
+            0x8000 => 1,
+            0x4000 => 2,
+            0x2000 => 3,
+            0x6000 => 4,
+            0x1000 => 5,
+            0xC000 => 6,
+            0xA000 => 7,
+            _ => 0,
+        }
+    }
+
+    fn file_type(&self) -> FileType {
+        match self.mode & 0xF000 {
+            0x4000 => FileType::Directory,
+            0x8000 => FileType::File,
+            0xA000 => FileType::Symlink,
+            _ => FileType::Other,
+        }
+    }
 }
 
-struct FindResult{
-    file_size:u32,
-    file_inode:usize,
-    parent_directory_offset:usize,
+// Un descriptor de grup de blocs (ext2_group_desc / ext4_group_desc).
+struct GroupDescriptor {
+    // Posició del descriptor dins de la taula principal.
+    offset: usize,
+    block_bitmap: u32,
+    inode_bitmap: u32,
+    inode_table: u32,
+    free_blocks_count: u32,
+    free_inodes_count: u32,
+    used_dirs_count: u32,
+}
+
+impl GroupDescriptor {
+    // desc_size és 32, o s_desc_size si el volum és de 64 bits. En aquest cas llegim també les parts altes.
+    fn parse(data: &[u8], offset: usize, desc_size: usize) -> Result<Self, ShooterError> {
+        let mut gd = GroupDescriptor {
+            offset,
+            block_bitmap: extract_u32(data, offset)?,
+            inode_bitmap: extract_u32(data, offset + 4)?,
+            inode_table: extract_u32(data, offset + 8)?,
+            free_blocks_count: extract_u16(data, offset + 12)? as u32,
+            free_inodes_count: extract_u16(data, offset + 14)? as u32,
+            used_dirs_count: extract_u16(data, offset + 16)? as u32,
+        };
+
+        if desc_size >= 64 {
+            // Només podem adreçar blocs de 32 bits.
+            for hi_offset in [offset + 32, offset + 36, offset + 40] {
+                if extract_u32(data, hi_offset)? != 0 {
+                    return Err(ShooterError::OutOfBounds { offset: hi_offset, len: 4 });
+                }
+            }
+            gd.free_blocks_count |= (extract_u16(data, offset + 44)? as u32) << 16;
+            gd.free_inodes_count |= (extract_u16(data, offset + 46)? as u32) << 16;
+            gd.used_dirs_count |= (extract_u16(data, offset + 48)? as u32) << 16;
+        }
+        Ok(gd)
+    }
+}
+
+// Una entrada de directori.
+struct DirEntry {
+    inode: u32,
+    name: String,
+    // Posició de l'entrada dins del volum.
+    offset: usize,
+    // Posició de l'entrada anterior dins del mateix bloc. None si és la primera del bloc.
+    prev_offset: Option<usize>,
+}
+
+// Un bloc de dades d'un inode: la seva posició dins del fitxer (en blocs) i el bloc físic. Un bloc
+// d'un extent no inicialitzat és de l'inode però encara no té dades: es llegeix com a zeros.
+#[derive(Clone, Copy)]
+struct DataBlock {
+    logical: u64,
+    block: u32,
+    initialized: bool,
 }
 
 impl Ext2 {
+    // Abans de qualsevol escriptura: si el volum té funcionalitats que no sabem mantenir, no el toquem.
+    fn check_writable(&self) -> Result<(), ShooterError> {
+        let incompat = self.feature_incompat & !SUPPORTED_INCOMPAT;
+        let ro_compat = self.feature_ro_compat & !SUPPORTED_RO_COMPAT;
+        if incompat == 0 && ro_compat == 0 {
+            return Ok(());
+        }
+
+        let mut features = feature_names(incompat, &FEATURES_INCOMPAT);
+        features.extend(feature_names(ro_compat, &FEATURES_RO_COMPAT));
+        Err(ShooterError::UnsupportedFeatures(features))
+    }
+
+    fn read_inode(&self, inode_num: usize) -> Result<Inode, ShooterError> {
+        let offset = self.compute_inode_offset(inode_num)?;
+        Ok(Inode {
+            mode: extract_u16(&self.data, offset)?,
+            size: extract_u32(&self.data, offset + 4)?,
+            links_count: extract_u16(&self.data, offset + 26)?,
+            atime: extract_u32(&self.data, offset + 8)?,
+            mtime: extract_u32(&self.data, offset + 16)?,
+            dtime: extract_u32(&self.data, offset + 20)?,
+        })
+    }
+
+    // Llegeix totes les entrades en ús d'un directori, incloent . i ..
+    fn read_dir(&self, inode_num: usize) -> Result<Vec<DirEntry>, ShooterError> {
+        let (blocks, _) = self.inode_blocks(inode_num)?;
+
+        let mut entries = vec![];
+        for DataBlock { block, .. } in blocks {
+            let block_offset = self.block_offset(block);
+            let mut i: usize = 0;
+            let mut prev_offset = None;
+            while i < self.block_size as usize {
+                let offset = block_offset + i;
+                let goal_inode = extract_u32(&self.data, offset)?;
+                let rec_len = extract_u16(&self.data, offset + 4)? as usize;
+                let name_len = extract_slice(&self.data, offset + 6, 1)?[0] as usize;
+
+                // Un rec_len mes petit que la capçalera ens faria iterar sempre sobre la mateixa entry.
+                if rec_len < 8 + name_len {
+                    return Err(ShooterError::OutOfBounds { offset, len: rec_len });
+                }
+
+                if goal_inode != 0 {
+                    entries.push(DirEntry {
+                        inode: goal_inode,
+                        name: extract_string(&self.data, offset + 8, name_len)?.to_string(),
+                        offset,
+                        prev_offset,
+                    });
+                }
+                prev_offset = Some(offset);
+                i += rec_len;
+            }
+        }
+        Ok(entries)
+    }
+
+    // Troba l'inode corresponent a un path, començant pel root.
+    fn resolve(&self, path: &str) -> Result<usize, ShooterError> {
+        match self.lookup(path)? {
+            Some(entry) => Ok(entry.inode as usize),
+            None => Ok(2),
+        }
+    }
+
+    // Troba l'entrada de directori de l'últim component del path. None si el path és el root.
+    fn lookup(&self, path: &str) -> Result<Option<DirEntry>, ShooterError> {
+        self.lookup_path(path, true)
+    }
+
+    // Com lookup, però amb follow_last a false l'últim component no se segueix encara que sigui un enllaç.
+    fn lookup_path(&self, path: &str, follow_last: bool) -> Result<Option<DirEntry>, ShooterError> {
+        let mut inode_num = 2;
+        let mut found = None;
+        let mut followed = 0;
+
+        // Components pendents, de l'últim al primer. Un enllaç hi afegeix els del seu destí.
+        let mut pending: Vec<String> = split_path(path).iter().rev().map(|c| c.to_string()).collect();
+        while let Some(component) = pending.pop() {
+            if !self.read_inode(inode_num)?.is_dir() {
+                return Err(ShooterError::FileNotFound);
+            }
+            let entry = self.read_dir(inode_num)?
+                .into_iter()
+                .find(|e| e.name == component)
+                .ok_or(ShooterError::FileNotFound)?;
+
+            let follow = self.follow_symlinks && (follow_last || !pending.is_empty());
+            let inode = self.read_inode(entry.inode as usize)?;
+            if follow && inode.file_type() == FileType::Symlink {
+                followed += 1;
+                if followed > MAX_SYMLINKS {
+                    return Err(ShooterError::SymlinkLoop);
+                }
+
+                // Un destí absolut torna a començar pel root. Un de relatiu continua pel directori de l'enllaç.
+                let target = self.link_target(entry.inode as usize, &inode)?;
+                if target.starts_with('/') {
+                    inode_num = 2;
+                    found = None;
+                }
+                pending.extend(split_path(&target).iter().rev().map(|c| c.to_string()));
+                continue;
+            }
+
+            inode_num = entry.inode as usize;
+            found = Some(entry);
+        }
+        Ok(found)
+    }
+
+    // Un enllaç simbòlic curt (fast symlink) guarda el destí dins de i_block i no té blocs de dades.
+    // i_blocks només hi pot comptar el bloc d'atributs estesos (i_file_acl).
+    fn is_fast_symlink(&self, inode_num: usize) -> Result<bool, ShooterError> {
+        let offset = self.compute_inode_offset(inode_num)?;
+        if extract_u16(&self.data, offset)? & 0xF000 != 0xA000 {
+            return Ok(false);
+        }
+        let ea_blocks = if extract_u32(&self.data, offset + 104)? != 0 { self.block_size / 512 } else { 0 };
+        Ok(self.i_blocks(inode_num)? <= ea_blocks as u64)
+    }
 
-    // Retorna l'offset donat un numero de block
-    fn get_offset(&self, block_number: usize) -> usize {
-        return if self.first_block == 1 {
-            // Block number == 1 -> Superblock.
-            1024 + (block_number - 1) * self.block_size as usize
+    // Path al qual apunta un enllaç simbòlic: dins de i_block o al seu primer bloc de dades.
+    fn link_target(&self, inode_num: usize, inode: &Inode) -> Result<String, ShooterError> {
+        let target = if self.is_fast_symlink(inode_num)? {
+            let offset = self.compute_inode_offset(inode_num)?;
+            extract_slice(&self.data, offset + 40, min(inode.size, 60) as usize)?.to_vec()
         } else {
-            block_number * self.block_size as usize
+            // El destí ocupa com a molt un bloc (PATH_MAX): una mida més gran és un inode corrupte.
+            if inode.size > self.block_size {
+                return Err(ShooterError::OutOfBounds { offset: self.compute_inode_offset(inode_num)? + 4, len: inode.size as usize });
+            }
+            let (blocks, _) = self.inode_blocks(inode_num)?;
+            let mut target = vec![];
+            self.write_blocks(&blocks, inode.size, &mut target)?;
+            target
         };
+        Ok(std::str::from_utf8(&target)?.to_string())
     }
 
-    // Calcula quin es el index maxim a l'interor del array de blocks del indode.
-    fn compute_max_index(&self, max_i_blocks: u32) -> u32{
-        return if max_i_blocks < 13 {
-            max_i_blocks
-        } else if max_i_blocks >= 13 && max_i_blocks <= self.indirect_block_row_count - 12 {
-            13
-        } else if max_i_blocks > self.indirect_block_row_count - 12 && max_i_blocks <= self.double_indirect_top_row {
-            14
+    // Cerca recursivament tots els fitxers anomenats filename a partir del directori inode_num. visited
+    // té els directoris ja recorreguts: si una entrada en torna a apuntar un, l'arbre té un cicle.
+    fn find_in_dir(&self, inode_num: usize, dir_path: &str, filename: &str, found: &mut Vec<FileEntry>, visited: &mut HashSet<usize>) -> Result<(), ShooterError> {
+        for entry in self.read_dir(inode_num)? {
+            //Evitem analitzar . i ..
+            if entry.name == "." || entry.name == ".." {
+                continue;
+            }
+
+            let path = join_path(dir_path, &entry.name);
+            if self.read_inode(entry.inode as usize)?.is_dir() {
+                if !visited.insert(entry.inode as usize) {
+                    return Err(ShooterError::DirectoryCycle);
+                }
+                // Is a directory. Recursive call.
+                self.find_in_dir(entry.inode as usize, &path, filename, found, visited)?;
+            } else if entry.name == filename {
+                found.push(self.file_entry(&entry.name, path, entry.inode as usize)?);
+            }
+        }
+        Ok(())
+    }
+
+    fn file_entry(&self, name: &str, path: String, inode_num: usize) -> Result<FileEntry, ShooterError> {
+        let inode = self.read_inode(inode_num)?;
+        let link_target = match inode.file_type() {
+            FileType::Symlink => Some(self.link_target(inode_num, &inode)?),
+            _ => None,
+        };
+        Ok(FileEntry {
+            name: name.to_string(),
+            path,
+            file_type: inode.file_type(),
+            size: inode.size,
+            inode: Some(inode_num as u32),
+            cluster: None,
+            mode: Some(inode.mode),
+            links: Some(inode.links_count),
+            created: None,
+            modified: Some(inode.mtime),
+            accessed: Some(inode.atime),
+            link_target,
+        })
+    }
+
+    // Retorna l'offset donat un numero de block. Tots els accessos a un bloc passen per aquí.
+    // El bloc 0 comença a l'inici del volum per qualsevol mida de bloc: amb blocs de 1K el superblock
+    // és el bloc 1, i amb blocs més grans és dins del bloc 0 (s_first_data_block ja ho reflecteix).
+    fn block_offset(&self, block_number: u32) -> usize {
+        block_number as usize * self.block_size as usize
+    }
+
+    // Mida d'un descriptor de grup: 32 bytes, o s_desc_size en els volums de 64 bits.
+    fn desc_size(&self) -> Result<usize, ShooterError> {
+        if self.feature_incompat & FEATURE_INCOMPAT_64BIT != 0 {
+            Ok(max(extract_u16(&self.data, 1024 + 254)? as usize, 32))
         } else {
-            15
+            Ok(32)
         }
     }
 
-    // Proporciona el offset desde l'inici del filesystem fins a l'inici de la taula d'inodes d'un inode concret.
-    fn compute_inode_table_start_offset(&self, inode_num: usize) -> usize{
-        let block_group_num = (inode_num - 1) / self.inodes_x_group as usize;
-        let block_group_offset = block_group_num * self.group_blocks_count as usize * self.block_size as usize;
-
-        let bg_inode_table = {
-            if block_group_num == 0 || block_group_num == 1 || is_power(block_group_num, 3) || is_power(block_group_num, 5) || is_power(block_group_num, 7) {
-                // Proporciona el block de la taula d'inodes del primer block group.
-                extract_u32(&self.data, self.get_offset(1 + self.first_block as usize + block_group_num * self.group_blocks_count as usize) + 8) as usize
-            } else {
-                // Si el block_group_num no és una potencia de 3, 5, 7 -> NO HI HA SUPERBLOCK NI BLOCK GROUP. Nomes cal saltar els 2 bitmaps.
-                2 * self.block_size as usize
+    // Llegeix la taula de descriptors de grup, que és al bloc que segueix al superblock.
+    fn read_group_descriptors(&self) -> Result<Vec<GroupDescriptor>, ShooterError> {
+        let desc_size = self.desc_size()?;
+        let group_count = (self.block_count.saturating_sub(self.first_block)).div_ceil(self.group_blocks_count) as usize;
+        let group_table_offset = self.block_offset(self.first_block.saturating_add(1));
+
+        (0..group_count)
+            .map(|group_num| GroupDescriptor::parse(&self.data, group_table_offset + group_num * desc_size, desc_size))
+            .collect()
+    }
+
+    // Suma blocs i inodes lliures al grup i al superblock (negatius si s'ocupen). dirs és el nombre de
+    // directoris alliberats. Només escrivim la part baixa dels camps del descriptor: els volums de 64 bits
+    // no es poden modificar.
+    fn add_free_counts(&mut self, group_num: usize, blocks: i32, inodes: i32, dirs: i32) -> Result<(), ShooterError> {
+        let gd = self.group_descriptors.get_mut(group_num).ok_or(ShooterError::OutOfBounds { offset: group_num, len: 1 })?;
+        gd.free_blocks_count = gd.free_blocks_count.saturating_add_signed(blocks);
+        gd.free_inodes_count = gd.free_inodes_count.saturating_add_signed(inodes);
+        gd.used_dirs_count = gd.used_dirs_count.saturating_add_signed(-dirs);
+        let (offset, free_blocks_count, free_inodes_count, used_dirs_count) = (gd.offset, gd.free_blocks_count, gd.free_inodes_count, gd.used_dirs_count);
+
+        // bg_free_blocks_count, bg_free_inodes_count i bg_used_dirs_count
+        save_u16(&mut self.data, offset + 12, free_blocks_count as u16)?;
+        save_u16(&mut self.data, offset + 14, free_inodes_count as u16)?;
+        save_u16(&mut self.data, offset + 16, used_dirs_count as u16)?;
+
+        // s_free_blocks_count i s_free_inodes_count
+        self.free_blocks = self.free_blocks.saturating_add_signed(blocks);
+        self.free_inodes = self.free_inodes.saturating_add_signed(inodes);
+        save_u32(&mut self.data, 1024 + 12, self.free_blocks)?;
+        save_u32(&mut self.data, 1024 + 16, self.free_inodes)
+    }
+
+    // Diu si un grup té còpia del superblock i de la taula de descriptors.
+    fn has_superblock_backup(&self, group_num: usize) -> Result<bool, ShooterError> {
+        if group_num == 0 {
+            return Ok(false);
+        }
+
+        // Amb sparse_super2 només hi ha còpia als (com a molt dos) grups de s_backup_bgs.
+        if self.feature_compat & FEATURE_COMPAT_SPARSE_SUPER2 != 0 {
+            let backup_bgs = [extract_u32(&self.data, 1024 + 0x24C)?, extract_u32(&self.data, 1024 + 0x250)?];
+            return Ok(backup_bgs.contains(&(group_num as u32)));
+        }
+
+        // Sense sparse_super, tots els grups en tenen. Amb sparse_super, el grup 1 i les potències de 3, 5 i 7.
+        if self.feature_ro_compat & FEATURE_RO_COMPAT_SPARSE_SUPER == 0 || group_num == 1 {
+            return Ok(true);
+        }
+        Ok([3, 5, 7].iter().any(|&base| {
+            let mut power = base;
+            while power < group_num {
+                power *= base;
             }
-        };
+            power == group_num
+        }))
+    }
+
+    // Retorna el descriptor d'un grup de blocs.
+    fn group(&self, group_num: usize) -> Result<&GroupDescriptor, ShooterError> {
+        self.group_descriptors.get(group_num).ok_or(ShooterError::OutOfBounds { offset: group_num, len: 1 })
+    }
+
+    // Retorna el grup d'un inode i la seva posició dins del grup.
+    fn inode_group(&self, inode_num: usize) -> Result<(usize, usize), ShooterError> {
+        // L'inode 0 no existeix, i no podem passar del nombre total d'inodes.
+        if inode_num == 0 || inode_num > self.inode_count as usize {
+            return Err(ShooterError::OutOfBounds { offset: inode_num, len: self.inode_size as usize });
+        }
+        Ok(((inode_num - 1) / self.inodes_x_group as usize, (inode_num - 1) % self.inodes_x_group as usize))
+    }
+
+    // Retorna el grup d'un bloc i la seva posició dins del grup. Els grups comencen a s_first_data_block.
+    fn block_group(&self, block: u32) -> Result<(usize, usize), ShooterError> {
+        if block < self.first_block || block >= self.block_count {
+            return Err(ShooterError::OutOfBounds { offset: block as usize, len: self.block_size as usize });
+        }
+        let relative_block = (block - self.first_block) as usize;
+        Ok((relative_block / self.group_blocks_count as usize, relative_block % self.group_blocks_count as usize))
+    }
 
-        return block_group_offset + self.get_offset(bg_inode_table);
+    // Proporciona el offset desde l'inici del filesystem fins a l'inici de la taula d'inodes d'un inode concret.
+    fn compute_inode_table_start_offset(&self, inode_num: usize) -> Result<usize, ShooterError> {
+        let (group_num, _) = self.inode_group(inode_num)?;
+        Ok(self.block_offset(self.group(group_num)?.inode_table))
     }
 
     // Donat un inode_num proporciona el offset a la seva posició a memoria desde l'inici del fs.
-    fn compute_inode_offset(&self, global_inode_num: usize) -> usize {
+    fn compute_inode_offset(&self, global_inode_num: usize) -> Result<usize, ShooterError> {
+        let inode_table_start_offset = self.compute_inode_table_start_offset(global_inode_num)?;
         let relative_inode_num = (global_inode_num - 1) % self.inodes_x_group as usize;
 
         // Donat un inode_num proporciona el offset a la seva posició desde l'inici de tot el fs.
-        return self.compute_inode_table_start_offset(global_inode_num) + relative_inode_num * self.inode_size as usize;
+        Ok(inode_table_start_offset + relative_inode_num * self.inode_size as usize)
     }
 
-    fn compute_indirect_max_loop(&self, i: u32, max_i_blocks: u32) -> u32{
-        return if i == 13 {
-            min(max_i_blocks, self.indirect_block_row_count - 12)
-        } else if i == 14 {
-            min(max_i_blocks, self.double_indirect_top_row)
-        } else {
-            // Iterem a muerte fins trobar una entrada amb valor 0.
-            0
+    // Apartat NOTES de http://manpages.ubuntu.com/manpages/precise/man8/e2undel.8.html
+    // Retorna el nombre de blocs de dades i de blocs d'índex alliberats, i els enllaços que queden.
+    fn delete_inode(&mut self, file_inode: usize, entry: &DirEntry) -> Result<(u32, u32, u16), ShooterError> {
+        // ---- Eliminar directory entry ----
+
+        self.remove_dir_entry(entry)?;
+
+        // ---- Treure l'enllaç ----
+
+        // Donat un inode_num proporciona el offset a la seva posició.
+        let offset = self.compute_inode_offset(file_inode)?;
+
+        // Si altres entrades de directori apunten al mateix inode (hard links), l'inode i les dades es queden.
+        // Un directori no pot tenir hard links: el seu comptador només inclou la pròpia entrada . i els .. dels fills.
+        let is_dir = self.read_inode(file_inode)?.is_dir();
+        let links = if is_dir { 0 } else { extract_u16(&self.data, offset + 26)?.saturating_sub(1) };
+        if links > 0 {
+            save_u16(&mut self.data, offset + 26, links)?;
+            // i_ctime: ha canviat l'inode.
+            save_u32(&mut self.data, offset + 12, current_time())?;
+            return Ok((0, 0, links));
         }
-    }
 
-    // Cerca tots els blocks a linterior d'un block indirecte. Si troba el fitxer, retorna la mida.
-    // layer especifica la profunditat. Si és 0, estem tractant single-indirect block.
-    // Sino, cal endinsarse més.
-    fn explore_indirect_block(&self, indirect_block_offset: u32, filename: &String, max_loop: u32, layer: u32, parent_directory_offset: usize) -> Option<FindResult> {
-        //println!("Exploring indirect block: {} {:x} MaxLoop is {} and layer is {}", indirect_block_offset, indirect_block_offset, max_loop, layer);
-        let mut k = 0;
-        while {
-            let block_number_inside_indirect_block = extract_u32(&self.data, (indirect_block_offset + 4 * k) as usize);
+        // ---- Alliberar els nodes dels bitmaps ----
+
+        // La posició de l'inode dins del seu grup és el bit del bitmap d'inodes.
+        let (group_num, relative_inode_num) = self.inode_group(file_inode)?;
+        let inode_bitmap_offset = self.block_offset(self.group(group_num)?.inode_bitmap);
+        let bitmap_byte_num = inode_bitmap_offset + relative_inode_num / 8;
 
-            let mut data_valid = block_number_inside_indirect_block != 0;
+        // Només comptem com a lliure el que realment estava marcat com a usat.
+        if clear_bit(&mut self.data,  bitmap_byte_num,(relative_inode_num % 8) as u8)? {
+            self.add_free_counts(group_num, 0, 1, is_dir as i32)?;
+        }
 
-            if data_valid {
-                let data_block_offset = block_number_inside_indirect_block * self.block_size;
+        // Crea una llista dels blocks emprats.
+        // Els blocs indirectes (o de l'arbre d'extents) també són de l'inode i s'han d'alliberar.
+        let (used_blocks, index_blocks) = self.inode_blocks(file_inode)?;
 
-                let file_size = {
-                    if layer == 0 {
-                        self.find_in_dir(data_block_offset, filename, parent_directory_offset)
-                    } else {
-                        // Les seguents layers iterem per tots els valors!
-                        self.explore_indirect_block(data_block_offset, filename, 0, layer - 1, parent_directory_offset)
-                    }
-                };
+        for block in used_blocks.iter().map(|b| b.block).chain(index_blocks.iter().copied()) {
+            self.free_block(block)?;
+        }
 
-                if file_size.is_some() {
-                    return file_size;
-                }
+        // ---- Modificar delete time "d_time" ----
+
+        // Es modifica el camp d_time
+        let time = current_time();
+        save_u32(&mut self.data, offset + 20, time)?;
+
+        // ---- Posem alguns camps d'interes a valors de delete ----
+        // Fora links.
+        save_u16(&mut self.data, offset + 26, 0)?;
+
+        // En mode soft, i_blocks i i_size es queden per poder recuperar l'inode amb undelete.
+        if !self.soft_delete {
+            // Iblocks a zero
+            self.set_i_blocks(file_inode, 0)?;
+
+            // Size a zero
+            save_u32(&mut self.data, offset + 4, 0)?;
+        }
+
+        // Retornem el nombre de blocks alliberats.
+        Ok((used_blocks.len() as u32, index_blocks.len() as u32, 0))
+    }
+
+    // Treu una entrada del seu directori, sense tocar l'inode al qual apunta.
+    fn remove_dir_entry(&mut self, entry: &DirEntry) -> Result<(), ShooterError> {
+        if let Some(pd_offset) = entry.prev_offset {
+            // Posem el rec_len del anterior apuntant al seguent
+            let rc_len_actual =  extract_u16(&self.data, pd_offset + 4)?;
+            let mida_dir_entry_a_borrar = extract_u16(&self.data, entry.offset + 4)?;
+            save_u16(&mut self.data,pd_offset + 4,rc_len_actual.wrapping_add(mida_dir_entry_a_borrar))?;
+        }
+
+        // Posem el dir entry a borrar amb inode id = 0. (not used)
+        // Si és la primera del bloc, no hi ha anterior on sumar el rec_len i aquesta entrada queda buida.
+        save_u32(&mut self.data, entry.offset, 0)
+    }
+
+    // Suma (o resta) un als enllaços d'un directori pel .. d'un fill. Amb dir_nlink, un comptador a 1
+    // vol dir "massa per comptar-los" i es queda així.
+    fn add_subdir_link(&mut self, dir_inode: usize, delta: i32) -> Result<(), ShooterError> {
+        let offset = self.compute_inode_offset(dir_inode)?;
+        let links = extract_u16(&self.data, offset + 26)?;
+        if links > 1 && links < EXT2_LINK_MAX {
+            save_u16(&mut self.data, offset + 26, (links as i32 + delta).clamp(1, EXT2_LINK_MAX as i32) as u16)?;
+        }
+        Ok(())
+    }
+
+    // Diu si el directori dir és descendant o un dels seus ancestres, pujant pels .. fins al root.
+    fn is_ancestor(&self, dir: usize, mut descendant: usize) -> Result<bool, ShooterError> {
+        // Un camí més llarg que el nombre d'inodes vol dir que els .. fan un cicle.
+        for _ in 0..self.inode_count {
+            if descendant == dir {
+                return Ok(true);
             }
-            // Si maxloop es 0, iterem fins a trobar un valor 0 en les dades
-            // Sino, iterem fins a max_loop.
-            if max_loop != 0 && k >= max_loop {
-                data_valid = false;
+            if descendant == 2 {
+                return Ok(false);
             }
+            descendant = self.read_dir(descendant)?
+                .iter()
+                .find(|e| e.name == "..")
+                .map(|e| e.inode as usize)
+                .ok_or(ShooterError::FileNotFound)?;
+        }
+        Err(ShooterError::DirectoryCycle)
+    }
+
+    // Marca un bloc com a lliure. Cada bloc pot ser d'un grup diferent, amb el seu propi bitmap de blocs.
+    fn free_block(&mut self, block: u32) -> Result<(), ShooterError> {
+        let (group_num, relative_block) = self.block_group(block)?;
+        let bitmap_byte_num = self.block_offset(self.group(group_num)?.block_bitmap) + relative_block / 8;
+        if clear_bit(&mut self.data,  bitmap_byte_num,(relative_block % 8) as u8)? {
+            self.add_free_counts(group_num, 1, 0, 0)?;
+        }
+        Ok(())
+    }
 
-            k = k + 1;
-            data_valid
-        } {}
-        None
+    // Diu si un bloc està marcat com a usat al bitmap del seu grup.
+    fn block_in_use(&self, block: u32) -> Result<bool, ShooterError> {
+        let (group_num, relative_block) = self.block_group(block)?;
+        let bitmap_byte_num = self.block_offset(self.group(group_num)?.block_bitmap) + relative_block / 8;
+        test_bit(&self.data, bitmap_byte_num, (relative_block % 8) as u8)
     }
 
-    // Cerca un fitxer de forma recursiva, retorna el Some(FindResult). Si no troba. retorna None.
-    // Find result conté mida de fitxer i inode d'aquest.
-    fn find_in_inode(&self, inode_num: usize, filename: &String, parent_directory_offset: usize) -> Option<FindResult> {
+    fn inode_in_use(&self, inode_num: usize) -> Result<bool, ShooterError> {
+        let (group_num, relative_inode_num) = self.inode_group(inode_num)?;
+        let bitmap_byte_num = self.block_offset(self.group(group_num)?.inode_bitmap) + relative_inode_num / 8;
+        test_bit(&self.data, bitmap_byte_num, (relative_inode_num % 8) as u8)
+    }
 
-        // Donat un inode_num proporciona el offset a la seva posició.
-        let offset = self.compute_inode_offset(inode_num);
+    // Marca un bloc com a usat, al revés que free_block.
+    fn use_block(&mut self, block: u32) -> Result<(), ShooterError> {
+        let (group_num, relative_block) = self.block_group(block)?;
+        let bitmap_byte_num = self.block_offset(self.group(group_num)?.block_bitmap) + relative_block / 8;
+        if !set_bit(&mut self.data, bitmap_byte_num, (relative_block % 8) as u8)? {
+            self.add_free_counts(group_num, -1, 0, 0)?;
+        }
+        Ok(())
+    }
 
-        let i_mode = extract_u16(&self.data, offset);
+    fn use_inode(&mut self, inode_num: usize, is_dir: bool) -> Result<(), ShooterError> {
+        let (group_num, relative_inode_num) = self.inode_group(inode_num)?;
+        let bitmap_byte_num = self.block_offset(self.group(group_num)?.inode_bitmap) + relative_inode_num / 8;
+        if !set_bit(&mut self.data, bitmap_byte_num, (relative_inode_num % 8) as u8)? {
+            self.add_free_counts(group_num, 0, -1, -(is_dir as i32))?;
+        }
+        Ok(())
+    }
 
-        // El inode_num correspon a un directori.
-        if (i_mode & 0x4000) == 0x4000 {
-            let max_i_blocks = extract_u32(&self.data, offset + 28) / (2 << extract_u32(&self.data, 1024 + 24));
+    // Retorna els blocs de dades i els blocs d'índex d'un inode.
+    fn inode_blocks(&self, inode_num: usize) -> Result<(Vec<DataBlock>, Vec<u32>), ShooterError> {
+        // Encara que falli, buidem el que s'hagi trobat perquè no ho vegi la següent cerca.
+        let result = self.search_for_inode_used_blocks(inode_num);
+        let blocks = (self.used_blocks.take(), self.index_blocks.take());
+        result.map(|_| blocks)
+    }
 
-            // Computa quin es l'index màxim del array.
-            let max_loop = self.compute_max_index(max_i_blocks);
+    // Escriu a out els primers size bytes del fitxer format pels blocs de dades blocks. Els blocs lògics
+    // que no hi són (forats) i els no inicialitzats es llegeixen com a zeros.
+    fn write_blocks(&self, blocks: &[DataBlock], size: u32, out: &mut dyn Write) -> Result<u64, ShooterError> {
+        let mut sorted: Vec<&DataBlock> = blocks.iter().filter(|b| b.initialized).collect();
+        sorted.sort_by_key(|b| b.logical);
+        let mut sorted = sorted.into_iter().peekable();
+        let zeros = vec![0; self.block_size as usize];
+
+        // Només copiem fins a i_size, no la resta de l'últim bloc.
+        let mut written = 0;
+        for logical in 0..size.div_ceil(self.block_size) as u64 {
+            // Si dos punters diuen ser el mateix bloc lògic, ens quedem amb el primer.
+            while sorted.next_if(|b| b.logical < logical).is_some() {}
+            let len = min(size - written, self.block_size) as usize;
+            let data = match sorted.next_if(|b| b.logical == logical) {
+                Some(b) => extract_slice(&self.data, self.block_offset(b.block), len)?,
+                None => &zeros[..len],
+            };
+            out.write_all(data).map_err(ShooterError::WriteFailed)?;
+            written += len as u32;
+        }
 
-            for i in 1..=max_loop {
+        if written != size {
+            return Err(ShooterError::OutOfBounds { offset: written as usize, len: (size - written) as usize });
+        }
+        Ok(written as u64)
+    }
 
-                if i < 13 {
-                    // Direct blocks: Els offsets son correctes, no fa falta fer gaire...
-                    let block_num = extract_u32(&self.data, offset + 40 + 4 * (i - 1) as usize);
-                    let data_block_offset =  block_num * self.block_size;
+    // Retorna un inode esborrat: i_dtime posat, sense enllaços i lliure al bitmap.
+    fn deleted_inode(&self, inode_num: usize) -> Result<Inode, ShooterError> {
+        if inode_num < self.first_inode as usize || inode_num > self.inode_count as usize {
+            return Err(ShooterError::FileNotFound);
+        }
+        let inode = self.read_inode(inode_num)?;
+        if inode.dtime == 0 || inode.links_count != 0 || inode.mode == 0 || self.inode_in_use(inode_num)? {
+            return Err(ShooterError::FileNotFound);
+        }
+        Ok(inode)
+    }
 
-                    let file_size = self.find_in_dir(data_block_offset, filename, parent_directory_offset);
+    // Blocs de dades i d'índex d'un inode esborrat, si encara són seus: cap d'ells pot estar en ús.
+    fn recoverable_blocks(&self, inode_num: usize, inode: &Inode) -> Result<(Vec<DataBlock>, Vec<u32>), ShooterError> {
+        // Un delete normal posa i_size i i_blocks a 0: no en queda res.
+        if inode.size == 0 {
+            return Err(ShooterError::NotRecoverable);
+        }
 
-                    if file_size.is_some() {
-                        return file_size;
-                    }
-                } else {
-                    // Pot ser simple - double o triple indirect.
-                    let indirect_block_offset = extract_u32(&self.data, offset + 40 + 4 * (i - 1) as usize) * self.block_size;
-                    let indirect_max_loop = self.compute_indirect_max_loop(i, max_i_blocks);
+        // Si el bloc d'índex o l'arbre d'extents s'ha reutilitzat, no en podem treure els blocs.
+        let (blocks, index_blocks) = self.inode_blocks(inode_num).map_err(|_| ShooterError::NotRecoverable)?;
+        for block in blocks.iter().map(|b| b.block).chain(index_blocks.iter().copied()) {
+            if self.block_in_use(block).unwrap_or(true) {
+                return Err(ShooterError::NotRecoverable);
+            }
+        }
+        Ok((blocks, index_blocks))
+    }
 
-                    let file_size = self.explore_indirect_block(indirect_block_offset, filename, indirect_max_loop, i - 13, parent_directory_offset);
+    // Afegeix una entrada al directori dir_inode, partint el rec_len d'una entrada que tingui espai de sobres.
+    fn add_dir_entry(&mut self, dir_inode: usize, name: &str, inode_num: u32, file_type: u8) -> Result<(), ShooterError> {
+        if name.len() > 255 {
+            return Err(ShooterError::NameTooLong);
+        }
+        let needed = (8 + name.len() + 3) & !3;
+
+        let (blocks_in_dir, _) = self.inode_blocks(dir_inode)?;
+        for &DataBlock { block, .. } in &blocks_in_dir {
+            let block_offset = self.block_offset(block);
+            let mut i: usize = 0;
+            while i < self.block_size as usize {
+                let offset = block_offset + i;
+                let entry_inode = extract_u32(&self.data, offset)?;
+                let rec_len = extract_u16(&self.data, offset + 4)? as usize;
+                let name_len = extract_slice(&self.data, offset + 6, 1)?[0] as usize;
+
+                if rec_len < 8 + name_len {
+                    return Err(ShooterError::OutOfBounds { offset, len: rec_len });
+                }
 
-                    if file_size.is_some() {
-                        return file_size;
+                // Una entrada buida (inode 0) no ocupa res. La resta ocupa la seva mida arrodonida a 4.
+                let used = if entry_inode == 0 { 0 } else { (8 + name_len + 3) & !3 };
+                if rec_len - used >= needed {
+                    if used > 0 {
+                        save_u16(&mut self.data, offset + 4, used as u16)?;
                     }
+                    self.write_dir_entry(offset + used, inode_num, (rec_len - used) as u16, name, file_type)?;
+                    return self.touch_dir(dir_inode);
                 }
+                i += rec_len;
             }
-        } else {
-            // S'executa quan hem trobat el inode del fitxer.
-            println!("File inode is {} Offset is dec: {} hex: {:x}", inode_num, offset, offset);
+        }
 
-            return Some(FindResult {
-                file_size: extract_u32(&self.data, offset + 4),
-                file_inode: inode_num,
-                parent_directory_offset
-            });
+        // No hi cap: afegim un bloc al final del directori, amb la nova entrada ocupant-lo sencer.
+        let dir_offset = self.compute_inode_offset(dir_inode)?;
+        let dir_size = extract_u32(&self.data, dir_offset + 4)?;
+        // Intentem que el bloc nou segueixi l'últim del directori.
+        let mut goal = match blocks_in_dir.last() {
+            Some(last) => last.block + 1,
+            None => self.group_first_block(self.inode_group(dir_inode)?.0),
+        };
+        let block = self.allocate_block(&mut goal)?;
+        let index_blocks = self.map_block(dir_inode, dir_size / self.block_size, block, &mut goal)?;
+        save_u32(&mut self.data, dir_offset + 4, dir_size + self.block_size)?;
+        self.add_i_blocks(dir_inode, 1 + index_blocks)?;
+
+        self.write_dir_entry(self.block_offset(block), inode_num, self.block_size as u16, name, file_type)?;
+        self.touch_dir(dir_inode)
+    }
+
+    // Comprova que es pot crear path: el pare ha de ser un directori sense cap entrada amb aquest nom.
+    // Retorna l'inode del pare i el nom de la nova entrada.
+    fn new_entry_parent<'a>(&self, path: &'a str) -> Result<(usize, &'a str), ShooterError> {
+        let components = split_path(path);
+        let (name, parent) = components.split_last().ok_or(ShooterError::RootDirectory)?;
+        let parent_inode = self.resolve(&parent.join("/"))?;
+        if !self.read_inode(parent_inode)?.is_dir() {
+            return Err(ShooterError::NotADirectory);
+        }
+        if self.read_dir(parent_inode)?.iter().any(|e| e.name == *name) {
+            return Err(ShooterError::AlreadyExists);
         }
-        None
+        Ok((parent_inode, name))
     }
 
-    // Analitza un directori que ocupa 1 sol bloc.
-    fn find_in_dir(&self, data_block_offset: u32, filename: &String, parent_directory_offset: usize) -> Option<FindResult> {
-        let mut i: usize = 0;
-        let mut last_rec = 0;
-        // Do while suuper apurat.
-        while {
-            let offset = data_block_offset as usize + i;
-            let goal_inode = extract_u32(&self.data, offset) as usize;
+    // Primer bloc d'un grup.
+    fn group_first_block(&self, group_num: usize) -> u32 {
+        self.first_block + group_num as u32 * self.group_blocks_count
+    }
 
-            let name_len = &self.data[offset + 6];
-            let found_filename = extract_string(&self.data, offset + 8, *name_len as usize).unwrap();
-            let file_type = self.data[offset + 7];
+    // Ocupa el primer inode lliure, començant pel grup goal_group (el del directori pare).
+    fn allocate_inode(&mut self, goal_group: usize, is_dir: bool) -> Result<usize, ShooterError> {
+        let group_count = self.group_descriptors.len();
+        for k in 0..group_count {
+            let group_num = (goal_group + k) % group_count;
+            if self.group(group_num)?.free_inodes_count == 0 {
+                continue;
+            }
+            for relative_inode_num in 0..self.inodes_x_group as usize {
+                let inode_num = group_num * self.inodes_x_group as usize + relative_inode_num + 1;
+                if inode_num < self.first_inode as usize || inode_num > self.inode_count as usize {
+                    continue;
+                }
+                if !self.inode_in_use(inode_num)? {
+                    self.use_inode(inode_num, is_dir)?;
+                    return Ok(inode_num);
+                }
+            }
+        }
+        Err(ShooterError::NoSpace)
+    }
 
-            let rec_len = extract_u16(&self.data, offset + 4);
+    // Ocupa el primer bloc lliure a partir de goal (i torna a començar pel principi si cal). El deixa a zero
+    // i avança goal, perquè els blocs d'un mateix fitxer quedin seguits.
+    fn allocate_block(&mut self, goal: &mut u32) -> Result<u32, ShooterError> {
+        let total = self.block_count - self.first_block;
+        let start = if *goal < self.first_block || *goal >= self.block_count { 0 } else { *goal - self.first_block };
 
-            // Si la entry no es fa servir + no hi ha seguent. Sortim de la recusio.
-            if goal_inode == 0 && rec_len == 0 {
-                return None;
+        let mut k = 0;
+        while k < total {
+            let block = self.first_block + (start + k) % total;
+            let (group_num, relative_block) = self.block_group(block)?;
+
+            // Un grup sense blocs lliures el saltem sencer.
+            if self.group(group_num)?.free_blocks_count == 0 {
+                k += self.group_blocks_count - relative_block as u32;
+                continue;
+            }
+            if !self.block_in_use(block)? {
+                self.use_block(block)?;
+                let block_offset = self.block_offset(block);
+                extract_slice_mut(&mut self.data, block_offset, self.block_size as usize)?.fill(0);
+                *goal = block + 1;
+                return Ok(block);
             }
+            k += 1;
+        }
+        Err(ShooterError::NoSpace)
+    }
 
-            // Check filetype:
-            if file_type != 2 {
-                // Not a dir. Check filename!
-                if file_type != 0 {
-                    if filename == found_filename {
+    // Assigna el bloc físic block a la posició lògica logical de l'inode, creant els blocs indirectes
+    // que calguin. Retorna quants blocs d'índex s'han hagut d'ocupar.
+    fn map_block(&mut self, inode_num: usize, logical: u32, block: u32, goal: &mut u32) -> Result<u32, ShooterError> {
+        let offset = self.compute_inode_offset(inode_num)?;
+        let i_flags = extract_u32(&self.data, offset + 32)?;
+        if self.feature_incompat & FEATURE_INCOMPAT_EXTENTS != 0 && i_flags & EXTENTS_FL != 0 {
+            return self.map_extent_block(offset + 40, logical, block, goal);
+        }
 
-                        // Busquem la mida del fitxer.
-                        // Aportem info sobre l'offset de la carpeta actual, el item de sobre al que coincideix el nom.
-                        let size = self.find_in_inode(goal_inode, filename, offset - last_rec);
-                        if size.is_some() {
-                            return size;
-                        }
-                    }
+        // Posició dins de i_block i índex dins de cada nivell d'indirecció.
+        let p = self.indirect_block_row_count as u64;
+        let logical = logical as u64;
+        let (root, indices) = if logical < 12 {
+            (logical as usize, vec![])
+        } else if logical < 12 + p {
+            (12, vec![logical - 12])
+        } else if logical < 12 + p + p * p {
+            let rest = logical - 12 - p;
+            (13, vec![rest / p, rest % p])
+        } else if logical < 12 + p + p * p + p * p * p {
+            let rest = logical - 12 - p - p * p;
+            (14, vec![rest / (p * p), (rest / p) % p, rest % p])
+        } else {
+            return Err(ShooterError::NoSpace);
+        };
+
+        let mut new_index_blocks = 0;
+        let mut pointer_offset = offset + 40 + 4 * root;
+        for index in indices {
+            let mut next = extract_u32(&self.data, pointer_offset)?;
+            if next == 0 {
+                next = self.allocate_block(goal)?;
+                save_u32(&mut self.data, pointer_offset, next)?;
+                new_index_blocks += 1;
+            }
+            pointer_offset = self.block_offset(next) + 4 * index as usize;
+        }
+        save_u32(&mut self.data, pointer_offset, block)?;
+        Ok(new_index_blocks)
+    }
+
+    // Afegeix un bloc al final d'un inode amb extents: allarga l'últim extent si el bloc és contigu, o n'hi
+    // afegeix un de nou a l'última fulla. Si la fulla és plena, penja una branca nova del primer node de
+    // sobre que tingui lloc, i si no n'hi ha cap, afegeix un nivell a l'arbre. Retorna els nodes nous.
+    fn map_extent_block(&mut self, root_offset: usize, logical: u32, block: u32, goal: &mut u32) -> Result<u32, ShooterError> {
+        let mut new_index_blocks = 0;
+        loop {
+            // Camí des de l'arrel fins a l'última fulla, sempre per l'últim fill.
+            let mut path = vec![root_offset];
+            let (mut entries, _, mut depth) = self.extent_header(root_offset)?;
+            while depth > 0 {
+                if entries == 0 || path.len() > EXTENT_MAX_DEPTH as usize {
+                    return Err(ShooterError::OutOfBounds { offset: root_offset, len: 12 });
                 }
-            } else {
-                // Is a directory. Recursive call.
+                let last = path[path.len() - 1] + 12 + 12 * (entries as usize - 1);
+                let child = self.block_offset(self.extent_block(last + 8, last + 4)?);
+                let (child_entries, _, child_depth) = self.extent_header(child)?;
+                if child_depth + 1 != depth {
+                    return Err(ShooterError::OutOfBounds { offset: child, len: 12 });
+                }
+                path.push(child);
+                entries = child_entries;
+                depth = child_depth;
+            }
 
-                //Evitem analitzar . i ..
-                if found_filename == "." || found_filename == ".." {
-                    // println!("Not analizing dir search because its me! Filename is {} Reclen is {}", found_filename, rec_len);
-                } else {
-                    // println!("Analizing a inode {} that is a dir!! Dirname is {} filetype is {}", goal_inode, found_filename, file_type);
-                    let size = self.find_in_inode(goal_inode, filename, parent_directory_offset);
-                    if size.is_some() {
-                        return size;
-                    }
+            let leaf = path[path.len() - 1];
+            if self.append_extent(leaf, logical, block)? {
+                return Ok(new_index_blocks);
+            }
+
+            // Primer node per sobre de la fulla amb lloc per un fill més.
+            let mut parent = None;
+            for &node in path.iter().rev().skip(1) {
+                let (entries, max_entries, _) = self.extent_header(node)?;
+                if entries < max_entries {
+                    parent = Some(node);
+                    break;
                 }
             }
 
-            // Add the rec_len to the iterator
-            i += rec_len as usize;
+            match parent {
+                Some(parent) => {
+                    // Una branca nova, amb un sol fill a cada nivell, fins a una fulla amb el bloc.
+                    let mut node = parent;
+                    let (_, _, mut depth) = self.extent_header(node)?;
+                    while depth > 0 {
+                        let child = self.allocate_block(goal)?;
+                        new_index_blocks += 1;
+                        depth -= 1;
+                        self.init_extent_node(self.block_offset(child), depth)?;
+                        self.append_extent_index(node, logical, child)?;
+                        node = self.block_offset(child);
+                    }
+                    self.append_extent(node, logical, block)?;
+                    return Ok(new_index_blocks);
+                }
+                None => {
+                    // L'arrel passa a un bloc nou, i l'arrel (dins de l'inode) queda amb un sol fill un nivell més amunt.
+                    let (_, _, root_depth) = self.extent_header(root_offset)?;
+                    if root_depth >= EXTENT_MAX_DEPTH {
+                        return Err(ShooterError::NoSpace);
+                    }
+                    let child = self.allocate_block(goal)?;
+                    new_index_blocks += 1;
+                    let child_offset = self.block_offset(child);
+                    let root = extract_slice(&self.data, root_offset, 60)?.to_vec();
+                    extract_slice_mut(&mut self.data, child_offset, 60)?.copy_from_slice(&root);
+                    save_u16(&mut self.data, child_offset + 4, ((self.block_size - 12) / 12) as u16)?;
+
+                    let first_logical = extract_u32(&self.data, root_offset + 12)?;
+                    save_u16(&mut self.data, root_offset + 2, 0)?;
+                    save_u16(&mut self.data, root_offset + 6, root_depth + 1)?;
+                    extract_slice_mut(&mut self.data, root_offset + 12, 48)?.fill(0);
+                    self.append_extent_index(root_offset, first_logical, child)?;
+                }
+            }
+        }
+    }
 
-            last_rec = rec_len as usize;
+    // Capçalera d'un node de l'arbre d'extents: (eh_entries, eh_max, eh_depth).
+    fn extent_header(&self, node_offset: usize) -> Result<(u16, u16, u16), ShooterError> {
+        if extract_u16(&self.data, node_offset)? != EXTENT_MAGIC {
+            return Err(ShooterError::OutOfBounds { offset: node_offset, len: 12 });
+        }
+        Ok((extract_u16(&self.data, node_offset + 2)?, extract_u16(&self.data, node_offset + 4)?, extract_u16(&self.data, node_offset + 6)?))
+    }
 
-            //Condicio d'exit del doWhile apanyat. Si el rec_len + indeex actual > block_size, retornem
-            (extract_u16(&self.data, offset + 4) as usize + i) <= self.block_size as usize
-        } {}
-        None
+    // Capçalera d'un node buit que ocupa un bloc sencer.
+    fn init_extent_node(&mut self, node_offset: usize, depth: u16) -> Result<(), ShooterError> {
+        save_u16(&mut self.data, node_offset, EXTENT_MAGIC)?;
+        save_u16(&mut self.data, node_offset + 2, 0)?;
+        save_u16(&mut self.data, node_offset + 4, ((self.block_size - 12) / 12) as u16)?;
+        save_u16(&mut self.data, node_offset + 6, depth)
     }
 
-    // Apartat NOTES de http://manpages.ubuntu.com/manpages/precise/man8/e2undel.8.html
-    // pd_offset = parent_directory_offset -> equival al offset del directory entry anterior en la carpeta.
-    fn delete_inode(&self, file_inode: usize, pd_offset: usize) -> Vec<u8> {
-        let mut new_data = self.data.to_vec();
+    // Afegeix el bloc a una fulla, allargant l'últim extent si és contigu. Retorna false si la fulla és plena.
+    fn append_extent(&mut self, leaf_offset: usize, logical: u32, block: u32) -> Result<bool, ShooterError> {
+        let (entries, max_entries, _) = self.extent_header(leaf_offset)?;
+        if entries > 0 {
+            let last = leaf_offset + 12 + 12 * (entries as usize - 1);
+            let first_logical = extract_u32(&self.data, last)?;
+            let len = extract_u16(&self.data, last + 4)?;
+            let start = self.extent_block(last + 6, last + 8)?;
+            // Un ee_len de més de 32768 és un extent no inicialitzat: no el barregem.
+            if len < 32768 && first_logical + len as u32 == logical && start + len as u32 == block {
+                save_u16(&mut self.data, last + 4, len + 1)?;
+                return Ok(true);
+            }
+        }
+        if entries >= max_entries {
+            return Ok(false);
+        }
 
-        // ---- Eliminar directory entry ----
+        // ee_block, ee_len, ee_start_hi, ee_start_lo
+        let new = leaf_offset + 12 + 12 * entries as usize;
+        save_u32(&mut self.data, new, logical)?;
+        save_u16(&mut self.data, new + 4, 1)?;
+        save_u16(&mut self.data, new + 6, 0)?;
+        save_u32(&mut self.data, new + 8, block)?;
+        save_u16(&mut self.data, leaf_offset + 2, entries + 1)?;
+        Ok(true)
+    }
 
-        // Posem el rec_len del anterior apuntant al seguent
-        let rc_len_actual =  extract_u16(&self.data, pd_offset + 4);
-        let mida_dir_entry_a_borrar = extract_u16(&self.data, pd_offset + rc_len_actual as usize + 4);
-        save_u16(&mut new_data,pd_offset + 4,rc_len_actual + mida_dir_entry_a_borrar);
+    // Afegeix un fill al final d'un node intern. El node ha de tenir lloc.
+    fn append_extent_index(&mut self, node_offset: usize, logical: u32, child: u32) -> Result<(), ShooterError> {
+        let (entries, _, _) = self.extent_header(node_offset)?;
+        // ei_block, ei_leaf_lo, ei_leaf_hi, ei_unused
+        let new = node_offset + 12 + 12 * entries as usize;
+        save_u32(&mut self.data, new, logical)?;
+        save_u32(&mut self.data, new + 4, child)?;
+        save_u32(&mut self.data, new + 8, 0)?;
+        save_u16(&mut self.data, node_offset + 2, entries + 1)
+    }
 
-        // Posem el dir entry a borrar amb inode id = 0. (not used)
-        save_u32(&mut new_data,pd_offset + mida_dir_entry_a_borrar as usize, 0);
+    // Canvia la mida d'un fitxer: allibera els blocs que queden després de la nova mida, ocupa els que hi
+    // falten des de l'antic final, i actualitza i_size, i_blocks i les dates. Els forats d'abans de l'antic
+    // final es queden com a forats. El que passa a formar part del fitxer queda a zero. Retorna els blocs de dades.
+    fn resize_inode(&mut self, inode_num: usize, size: u32) -> Result<Vec<DataBlock>, ShooterError> {
+        let offset = self.compute_inode_offset(inode_num)?;
+        let old_size = extract_u32(&self.data, offset + 4)?;
+        let (mut blocks, index_blocks) = self.inode_blocks(inode_num)?;
+        let count = size.div_ceil(self.block_size) as u64;
+        let i_flags = extract_u32(&self.data, offset + 32)?;
+        let extents = self.feature_incompat & FEATURE_INCOMPAT_EXTENTS != 0 && i_flags & EXTENTS_FL != 0;
+
+        // Tornar a construir el mapa inicialitzaria els extents no inicialitzats, i el que s'hi escrivís no
+        // es llegiria: no els sabem mantenir.
+        if blocks.iter().any(|b| !b.initialized) {
+            return Err(ShooterError::Unsupported);
+        }
 
-        // ---- Alliberar els nodes dels bitmaps ----
+        if blocks.iter().any(|b| b.logical >= count) {
+            // Tornem a construir el mapa amb els blocs que queden: és més senzill que desfer per la cua
+            // els blocs indirectes o l'arbre d'extents.
+            let freed: Vec<u32> = blocks.iter()
+                .filter(|b| b.logical >= count)
+                .map(|b| b.block)
+                .chain(index_blocks.iter().copied())
+                .collect();
+            for &block in &freed {
+                self.free_block(block)?;
+            }
+            blocks.retain(|b| b.logical < count);
+            // Els extents s'han d'afegir en ordre.
+            blocks.sort_by_key(|b| b.logical);
+
+            extract_slice_mut(&mut self.data, offset + 40, 60)?.fill(0);
+            if extents {
+                // L'arrel dins de i_block té lloc per 4 extents.
+                save_u16(&mut self.data, offset + 40, EXTENT_MAGIC)?;
+                save_u16(&mut self.data, offset + 44, 4)?;
+            }
+            let mut goal = match blocks.first() {
+                Some(first) => first.block,
+                None => self.group_first_block(self.inode_group(inode_num)?.0),
+            };
+            let mut new_index_blocks = 0;
+            for b in &blocks {
+                new_index_blocks += self.map_block(inode_num, b.logical as u32, b.block, &mut goal)?;
+            }
 
-        // Computem la posicio de la taula d'inodes del inode a borrar.
-        let inode_table_start_offset: usize= self.compute_inode_table_start_offset(file_inode);
+            // i_blocks pot incloure altres blocs (atributs estesos): només hi restem la diferència.
+            let freed_sectors = (freed.len() as u32).saturating_sub(new_index_blocks) as u64 * (self.block_size / 512) as u64;
+            self.set_i_blocks(inode_num, self.i_blocks(inode_num)?.saturating_sub(freed_sectors))?;
+        }
 
-        // Restem 1 block a la localitzacio de la taula per arribar al inode bitmap.
-        let inode_bitmap_offset = inode_table_start_offset - 1 * self.block_size as usize;
-        let relative_inode_num = (file_inode - 1) % self.inodes_x_group as usize;
-        let bitmap_byte_num = inode_bitmap_offset + relative_inode_num / 8;
+        if size > old_size {
+            // Des del bloc on acabava el fitxer (que pot ser un forat) fins al nou final.
+            let mapped: HashSet<u64> = blocks.iter().map(|b| b.logical).collect();
+            let missing: Vec<u64> = ((old_size / self.block_size) as u64..count).filter(|l| !mapped.contains(l)).collect();
 
-        clear_bit(&mut new_data,  bitmap_byte_num,(relative_inode_num % 8) as u8);
+            // Els extents només es poden afegir al final de l'arbre.
+            if extents && missing.first().is_some_and(|&first| blocks.iter().any(|b| b.logical > first)) {
+                return Err(ShooterError::Unsupported);
+            }
 
-        // Restem 2 block a la localitzacio de la taula per arribar al block bitmap.
-        let data_block_bitmap_offset = inode_table_start_offset - 2 * self.block_size as usize;
+            let mut goal = match blocks.iter().max_by_key(|b| b.logical) {
+                Some(last) => last.block + 1,
+                None => self.group_first_block(self.inode_group(inode_num)?.0),
+            };
+            let mut new_blocks = 0;
+            for logical in missing {
+                let block = self.allocate_block(&mut goal)?;
+                new_blocks += 1 + self.map_block(inode_num, logical as u32, block, &mut goal)?;
+                blocks.push(DataBlock { logical, block, initialized: true });
+            }
+            self.add_i_blocks(inode_num, new_blocks)?;
+
+            // Els blocs nous ja són a zero, però la resta de l'últim bloc després de la mida antiga pot
+            // tenir dades velles.
+            for b in &blocks {
+                let from = (old_size as u64).saturating_sub(b.logical * self.block_size as u64);
+                if from < self.block_size as u64 {
+                    let block_offset = self.block_offset(b.block);
+                    extract_slice_mut(&mut self.data, block_offset + from as usize, (self.block_size as u64 - from) as usize)?.fill(0);
+                }
+            }
+        }
 
-        // Crea una llista dels blocks emprats i la guarda a self.used_blocks.
-        self.search_for_inode_used_blocks(file_inode);
-        let mut used_blocks = self.used_blocks.borrow_mut();
+        // i_size i la seva part alta (i_size_high), que no fem servir.
+        let time = current_time();
+        save_u32(&mut self.data, offset + 4, size)?;
+        save_u32(&mut self.data, offset + 108, 0)?;
+        save_u32(&mut self.data, offset + 12, time)?;
+        save_u32(&mut self.data, offset + 16, time)?;
+        Ok(blocks)
+    }
 
-        for block in used_blocks.iter() {
-            let bitmap_byte_num = data_block_bitmap_offset + *block as usize / 8;
-            clear_bit(&mut new_data,  bitmap_byte_num,(*block as usize % 8) as u8);
+    // Escriu contents a partir del byte pos d'un fitxer amb els blocs de dades blocks, que han de ser prou.
+    fn write_at(&mut self, blocks: &[DataBlock], pos: u32, contents: &[u8]) -> Result<(), ShooterError> {
+        let by_logical: HashMap<u64, u32> = blocks.iter().map(|b| (b.logical, b.block)).collect();
+        let mut pos = pos;
+        let mut rest = contents;
+        while !rest.is_empty() {
+            let block = *by_logical.get(&((pos / self.block_size) as u64)).ok_or(ShooterError::OutOfBounds { offset: pos as usize, len: rest.len() })?;
+            let within = pos % self.block_size;
+            let len = min((self.block_size - within) as usize, rest.len());
+            let block_offset = self.block_offset(block) + within as usize;
+            extract_slice_mut(&mut self.data, block_offset, len)?.copy_from_slice(&rest[..len]);
+            pos += len as u32;
+            rest = &rest[len..];
         }
+        Ok(())
+    }
 
-        // ---- Modificar delete time "d_time" ----
+    // Un fitxer que es pot redimensionar: ha de ser un fitxer regular. Retorna la seva entrada de directori.
+    fn regular_file(&self, path: &str) -> Result<DirEntry, ShooterError> {
+        let entry = self.lookup(path)?.ok_or(ShooterError::RootDirectory)?;
+        match self.read_inode(entry.inode as usize)?.file_type() {
+            FileType::File => Ok(entry),
+            FileType::Directory => Err(ShooterError::IsADirectory),
+            FileType::Symlink => Err(ShooterError::IsASymlink),
+            FileType::Other => Err(ShooterError::Unsupported),
+        }
+    }
 
-        // Donat un inode_num proporciona el offset a la seva posició.
-        let offset = self.compute_inode_offset(file_inode);
+    // Suma blocs (de mida block_size) al camp i_blocks, que compta sectors de 512 bytes.
+    fn add_i_blocks(&mut self, inode_num: usize, blocks: u32) -> Result<(), ShooterError> {
+        let i_blocks = self.i_blocks(inode_num)?;
+        self.set_i_blocks(inode_num, i_blocks + blocks as u64 * (self.block_size / 512) as u64)
+    }
 
-        // Es modifica el camp d_time
-        let time = current_time();
-        save_u32(&mut new_data, offset + 20, time);
+    // Valor de i_blocks en sectors de 512 bytes. Amb huge_file hi afegim la part alta (l_i_blocks_hi), i si
+    // l'inode té EXT4_HUGE_FILE_FL el camp compta blocs del volum.
+    fn i_blocks(&self, inode_num: usize) -> Result<u64, ShooterError> {
+        let offset = self.compute_inode_offset(inode_num)?;
+        let mut i_blocks = extract_u32(&self.data, offset + 28)? as u64;
+        if self.feature_ro_compat & FEATURE_RO_COMPAT_HUGE_FILE != 0 {
+            i_blocks |= (extract_u16(&self.data, offset + 116)? as u64) << 32;
+            if extract_u32(&self.data, offset + 32)? & HUGE_FILE_FL != 0 {
+                i_blocks *= (self.block_size / 512) as u64;
+            }
+        }
+        Ok(i_blocks)
+    }
 
-        // ---- Posem alguns camps d'interes a valors de delete ----
-        // Fora links.
-        save_u16(&mut new_data, offset + 26, 0);
+    // Guarda i_blocks en sectors de 512 bytes. Sense huge_file, l_i_blocks_hi no existeix i el valor ha de
+    // cabre en 32 bits. Amb huge_file, si no cap en 48 bits de sectors passem a comptar blocs del volum.
+    fn set_i_blocks(&mut self, inode_num: usize, sectors: u64) -> Result<(), ShooterError> {
+        let offset = self.compute_inode_offset(inode_num)?;
+        if self.feature_ro_compat & FEATURE_RO_COMPAT_HUGE_FILE == 0 {
+            return save_u32(&mut self.data, offset + 28, u32::try_from(sectors).map_err(|_| ShooterError::NoSpace)?);
+        }
 
-        // Iblocks a zero
-        save_u32(&mut new_data, offset + 28, 0);
+        let i_flags = extract_u32(&self.data, offset + 32)?;
+        let (value, i_flags) = if sectors < 1 << 48 {
+            (sectors, i_flags & !HUGE_FILE_FL)
+        } else {
+            (sectors / (self.block_size / 512) as u64, i_flags | HUGE_FILE_FL)
+        };
+        save_u32(&mut self.data, offset + 28, value as u32)?;
+        save_u16(&mut self.data, offset + 116, (value >> 32) as u16)?;
+        save_u32(&mut self.data, offset + 32, i_flags)
+    }
 
-        // Size a zero
-        save_u32(&mut new_data, offset + 4, 0);
+    // Inicialitza un inode nou: mode, el mateix propietari que el directori pare, timestamps actuals i un enllaç.
+    fn init_inode(&mut self, inode_num: usize, mode: u16, parent_inode: usize) -> Result<(), ShooterError> {
+        // i_uid, i_gid i les seves parts altes (l_i_uid_high, l_i_gid_high).
+        let parent_offset = self.compute_inode_offset(parent_inode)?;
+        let owner: Vec<(usize, u16)> = [2, 24, 120, 122].iter()
+            .map(|&field| Ok((field, extract_u16(&self.data, parent_offset + field)?)))
+            .collect::<Result<_, ShooterError>>()?;
+
+        let offset = self.compute_inode_offset(inode_num)?;
+        extract_slice_mut(&mut self.data, offset, self.inode_size as usize)?.fill(0);
+        save_u16(&mut self.data, offset, mode)?;
+        for (field, value) in owner {
+            save_u16(&mut self.data, offset + field, value)?;
+        }
 
-        // Retornem les dades modificades.
-        return new_data;
+        let time = current_time();
+        save_u32(&mut self.data, offset + 8, time)?;
+        save_u32(&mut self.data, offset + 12, time)?;
+        save_u32(&mut self.data, offset + 16, time)?;
+        save_u16(&mut self.data, offset + 26, 1)?;
+
+        // Els inodes grans comencen amb i_extra_isize: fem servir el que demana el superblock (s_want_extra_isize).
+        if self.inode_size > 128 {
+            let extra_isize = min(extract_u16(&self.data, 1024 + 0x15E)?, self.inode_size - 128);
+            save_u16(&mut self.data, offset + 128, extra_isize)?;
+        }
+        Ok(())
     }
 
-    // Donat un inode, retorna els blocks emprats
-    fn search_for_inode_used_blocks(&self, inode_num: usize){
+    // Escriu una entrada de directori (ext2_dir_entry_2) a offset.
+    fn write_dir_entry(&mut self, offset: usize, inode_num: u32, rec_len: u16, name: &str, file_type: u8) -> Result<(), ShooterError> {
+        save_u32(&mut self.data, offset, inode_num)?;
+        save_u16(&mut self.data, offset + 4, rec_len)?;
+        // Sense la funcionalitat filetype, el byte del tipus és la part alta de name_len.
+        let file_type = if self.feature_incompat & FEATURE_INCOMPAT_FILETYPE != 0 { file_type } else { 0 };
+        extract_slice_mut(&mut self.data, offset + 6, 2)?.copy_from_slice(&[name.len() as u8, file_type]);
+        extract_slice_mut(&mut self.data, offset + 8, name.len())?.copy_from_slice(name.as_bytes());
+        Ok(())
+    }
 
-        // Donat un inode_num proporciona el offset a la seva posició.
-        let offset = self.compute_inode_offset(inode_num);
+    // Un directori modificat: actualitzem mtime i ctime. Si tenia índex (dir_index), el deixem de fer servir
+    // perquè no el mantenim; el directori continua sent vàlid com a llista lineal.
+    fn touch_dir(&mut self, dir_inode: usize) -> Result<(), ShooterError> {
+        let offset = self.compute_inode_offset(dir_inode)?;
+        let time = current_time();
+        save_u32(&mut self.data, offset + 12, time)?;
+        save_u32(&mut self.data, offset + 16, time)?;
+        let i_flags = extract_u32(&self.data, offset + 32)?;
+        save_u32(&mut self.data, offset + 32, i_flags & !INDEX_FL)
+    }
 
-        let max_i_blocks = extract_u32(&self.data, offset + 28) / (2 << extract_u32(&self.data, 1024 + 24));
+    // Donat un inode, deixa els blocks emprats a self.used_blocks i els blocs d'índex a self.index_blocks.
+    fn search_for_inode_used_blocks(&self, inode_num: usize) -> Result<(), ShooterError> {
+        self.used_blocks.borrow_mut().clear();
+        self.index_blocks.borrow_mut().clear();
 
-        // Computa quin es l'index màxim del array.
-        let max_loop = self.compute_max_index(max_i_blocks);
+        // El destí d'un enllaç curt ocupa i_block: no són punters a blocs. Els dispositius hi guarden
+        // el seu número, i les pipes i els sockets no tenen dades.
+        if self.is_fast_symlink(inode_num)? || self.read_inode(inode_num)?.file_type() == FileType::Other {
+            return Ok(());
+        }
 
-        for i in 1..=max_loop {
+        // Donat un inode_num proporciona el offset a la seva posició.
+        let offset = self.compute_inode_offset(inode_num)?;
 
-            if i < 13 {
-                // Direct blocks: Els offsets son correctes, no fa falta fer gaire...
-                let block_num = extract_u32(&self.data, offset + 40 + 4 * (i - 1) as usize);
+        // En ext4, i_block pot contenir l'arrel d'un arbre d'extents en lloc dels punters.
+        let i_flags = extract_u32(&self.data, offset + 32)?;
+        if self.feature_incompat & FEATURE_INCOMPAT_EXTENTS != 0 && i_flags & EXTENTS_FL != 0 {
+            return self.search_for_inode_blocks_inside_extents(offset + 40, 60, EXTENT_MAX_DEPTH);
+        }
 
-                {
-                    let mut used_bocks = self.used_blocks.borrow_mut();
-                    used_bocks.push(block_num);
+        // Els 12 primers punters de i_block són directes, i els tres últims són el simple, el doble i el
+        // triple indirecte. Un punter a 0 és un forat i el fitxer pot continuar després: els mirem tots.
+        let p = self.indirect_block_row_count as u64;
+        let mut first_logical = 12;
+        for i in 0..15 {
+            let block_num = extract_u32(&self.data, offset + 40 + 4 * i)?;
+            if i < 12 {
+                if block_num != 0 {
+                    self.used_blocks.borrow_mut().push(DataBlock { logical: i as u64, block: block_num, initialized: true });
                 }
-
             } else {
-                // Pot ser simple - double o triple indirect.
-                let indirect_block_offset = extract_u32(&self.data, offset + 40 + 4 * (i - 1) as usize) * self.block_size;
-                let indirect_max_loop = self.compute_indirect_max_loop(i, max_i_blocks);
-                self.search_for_inode_blocks_inside_indirects(indirect_block_offset, indirect_max_loop, i - 13);
+                let layer = (i - 12) as u32;
+                if block_num != 0 {
+                    self.search_for_inode_blocks_inside_indirects(block_num, layer, first_logical)?;
+                }
+                first_logical += p.pow(layer + 1);
             }
         }
+        Ok(())
     }
 
-    // Troba els blocks emprats per un inode a l'interior dels blocks indirectes.
-    fn search_for_inode_blocks_inside_indirects(&self, indirect_block_offset: u32, max_loop: u32, layer: u32) {
-        let mut k = 0;
-        while {
-            let block_number_inside_indirect_block = extract_u32(&self.data, (indirect_block_offset + 4 * k) as usize);
+    // Troba els blocks emprats per un inode a l'interior dels blocks indirectes. El primer punter del
+    // bloc apunta al bloc lògic first_logical (o al bloc indirecte que el conté, si layer no és 0).
+    fn search_for_inode_blocks_inside_indirects(&self, indirect_block: u32, layer: u32, first_logical: u64) -> Result<(), ShooterError> {
+        self.index_blocks.borrow_mut().push(indirect_block);
+        let indirect_block_offset = self.block_offset(indirect_block);
+        // Blocs de dades que hi ha per sota de cada punter d'aquest nivell.
+        let span = (self.indirect_block_row_count as u64).pow(layer);
 
-            let mut data_valid = block_number_inside_indirect_block != 0;
+        for k in 0..self.indirect_block_row_count as u64 {
+            let block_number_inside_indirect_block = extract_u32(&self.data, indirect_block_offset + 4 * k as usize)?;
 
-            if data_valid {
-                let data_block_offset = block_number_inside_indirect_block * self.block_size;
+            // Un punter a 0 és un forat, però els següents encara poden tenir blocs.
+            if block_number_inside_indirect_block == 0 {
+                continue;
+            }
 
-                if layer == 0 {
-                    // Hem trobat ultim nivell, afegim data.
-                    {
-                        let mut used_bocks = self.used_blocks.borrow_mut();
-                        used_bocks.push(block_number_inside_indirect_block);
-                    }
+            let logical = first_logical + k * span;
+            if layer == 0 {
+                // Hem trobat ultim nivell, afegim data.
+                self.used_blocks.borrow_mut().push(DataBlock { logical, block: block_number_inside_indirect_block, initialized: true });
+            } else {
+                self.search_for_inode_blocks_inside_indirects(block_number_inside_indirect_block, layer - 1, logical)?;
+            }
+        }
+        Ok(())
+    }
 
-                } else {
-                    // Les seguents layers iterem per tots els valors!
-                    self.search_for_inode_blocks_inside_indirects(data_block_offset, 0, layer - 1);
+    // Troba els blocks emprats per un inode recorrent un node de l'arbre d'extents (i els seus fills).
+    // El node ocupa node_len bytes (60 a l'inode, un bloc sencer la resta) i pot tenir com a molt
+    // max_depth nivells per sota. Cada fill ha de ser un nivell més avall, així no hi pot haver cicles.
+    fn search_for_inode_blocks_inside_extents(&self, node_offset: usize, node_len: usize, max_depth: u16) -> Result<(), ShooterError> {
+        // Capçalera: eh_magic, eh_entries, eh_max, eh_depth, eh_generation.
+        let magic = extract_u16(&self.data, node_offset)?;
+        let entries = extract_u16(&self.data, node_offset + 2)? as usize;
+        let depth = extract_u16(&self.data, node_offset + 6)?;
+
+        if magic != EXTENT_MAGIC || depth > max_depth || 12 + 12 * entries > node_len {
+            return Err(ShooterError::OutOfBounds { offset: node_offset, len: 12 });
+        }
+
+        for k in 0..entries {
+            let entry_offset = node_offset + 12 + 12 * k;
+
+            if depth == 0 {
+                // Fulla (ext4_extent): ee_block, ee_len, ee_start_hi, ee_start_lo. L'extent comença al bloc
+                // lògic ee_block: entre un extent i el següent hi pot haver un forat.
+                // Un ee_len de mes de 32768 indica un extent reservat però no inicialitzat, de ee_len & 0x7FFF
+                // blocs que s'han de llegir com a zeros.
+                let first_logical = extract_u32(&self.data, entry_offset)? as u64;
+                let (len, initialized) = match extract_u16(&self.data, entry_offset + 4)? {
+                    len if len > 32768 => (len & 0x7FFF, false),
+                    len => (len, true),
+                };
+                let start = self.extent_block(entry_offset + 6, entry_offset + 8)?;
+                if start as u64 + len as u64 > self.block_count as u64 {
+                    return Err(ShooterError::OutOfBounds { offset: entry_offset, len: 12 });
                 }
 
+                let mut used_bocks = self.used_blocks.borrow_mut();
+                used_bocks.extend((0..len as u32).map(|k| DataBlock { logical: first_logical + k as u64, block: start + k, initialized }));
+            } else {
+                // Node intern (ext4_extent_idx): ei_block, ei_leaf_lo, ei_leaf_hi.
+                let leaf = self.extent_block(entry_offset + 8, entry_offset + 4)?;
+                self.index_blocks.borrow_mut().push(leaf);
+                self.search_for_inode_blocks_inside_extents(self.block_offset(leaf), self.block_size as usize, depth - 1)?;
             }
-            // Si maxloop es 0, iterem fins a trobar un valor 0 en les dades
-            // Sino, iterem fins a max_loop.
-            if max_loop != 0 && k >= max_loop {
-                data_valid = false;
-            }
+        }
+        Ok(())
+    }
 
-            k = k + 1;
-            data_valid
-        } {}
+    // Llegeix un número de bloc de 48 bits partit en una part alta (u16) i una baixa (u32).
+    fn extent_block(&self, hi_offset: usize, lo_offset: usize) -> Result<u32, ShooterError> {
+        // Només podem adreçar blocs de 32 bits, igual que la resta del volum.
+        if extract_u16(&self.data, hi_offset)? != 0 {
+            return Err(ShooterError::OutOfBounds { offset: hi_offset, len: 2 });
+        }
+        extract_u32(&self.data, lo_offset)
     }
 }
 
 impl Filesystem for Ext2 {
-    fn new(gv: GenericVolume) -> Self {
+    fn new(gv: GenericVolume) -> Result<Self, ShooterError> {
+
+        // Ext2 no admet blocs de mes de 64KiB.
+        if extract_u32(&gv.data, 1024 + 24)? > 6 {
+            return Err(ShooterError::UnrecognizedFormat);
+        }
 
-        let block_size = extract_log_u32(&gv.data, 1024 + 24);
+        let block_size = extract_log_u32(&gv.data, 1024 + 24)?;
         // Quantitat de rows dels blocs indirectes. Emprat a find i delete.
         let indirect_block_row_count =  block_size / 4;
 
-        Ext2 {
-            file_name: gv.file_name,
-            vol_name: gv.vol_name,
+        let inodes_x_group = extract_u32(&gv.data, 1024 + 40)?;
+        let group_blocks_count = extract_u32(&gv.data, 1024 + 32)?;
+        let inode_size = extract_u16(&gv.data, 1024 + 88)?;
+        if inodes_x_group == 0 || group_blocks_count == 0 || inode_size == 0 {
+            return Err(ShooterError::UnrecognizedFormat);
+        }
+
+        // El volum ha de contenir tots els blocs que declara el superblock.
+        let block_count = extract_u32(&gv.data, 1024 + 4)?;
+        let expected = block_count as usize * block_size as usize;
+        if gv.data.len() < expected {
+            return Err(ShooterError::TruncatedImage { expected, found: gv.data.len() });
+        }
+
+        let mut ext2 = Ext2 {
+            path: gv.path,
 
             used_blocks: RefCell::new(vec![]),
+            index_blocks: RefCell::new(vec![]),
+            group_descriptors: vec![],
             indirect_block_row_count,
-            double_indirect_top_row: indirect_block_row_count - 12 + block_size * block_size,
 
-            inode_count: extract_u32(&gv.data, 1024),
-            free_inodes: extract_u32(&gv.data, 1024 + 16),
-            inodes_x_group: extract_u32(&gv.data, 1024 + 40),
-            first_inode: extract_u32(&gv.data, 1024 + 84),
-            inode_size: extract_u16(&gv.data, 1024 + 88),
+            inode_count: extract_u32(&gv.data, 1024)?,
+            free_inodes: extract_u32(&gv.data, 1024 + 16)?,
+            inodes_x_group,
+            first_inode: extract_u32(&gv.data, 1024 + 84)?,
+            inode_size,
 
             block_size,
-            block_count: extract_u32(&gv.data, 1024 + 4),
-            rsvd_blocks: extract_u32(&gv.data, 1024 + 8),
-            free_blocks: extract_u32(&gv.data, 1024 + 12),
-            first_block: extract_u32(&gv.data, 1024 + 20),
-            group_blocks_count: extract_u32(&gv.data, 1024 + 32),
-            group_frags_count: extract_u32(&gv.data, 1024 + 36),
+            block_count,
+            rsvd_blocks: extract_u32(&gv.data, 1024 + 8)?,
+            free_blocks: extract_u32(&gv.data, 1024 + 12)?,
+            first_block: extract_u32(&gv.data, 1024 + 20)?,
+            group_blocks_count,
+            group_frags_count: extract_u32(&gv.data, 1024 + 36)?,
+
+            volume_name: extract_string_terminated(&gv.data, 1024 + 120, 16)?.to_string(),
 
-            volume_name: extract_string_terminated(&gv.data, 1024 + 120, 16).unwrap().parse().unwrap(),
+            feature_compat: extract_u32(&gv.data, 1024 + 92)?,
+            feature_incompat: extract_u32(&gv.data, 1024 + 96)?,
+            feature_ro_compat: extract_u32(&gv.data, 1024 + 100)?,
 
-            last_check: extract_u32(&gv.data, 1024 + 64),
-            last_mount: extract_u32(&gv.data, 1024 + 44),
-            last_write: extract_u32(&gv.data, 1024 + 48),
+            last_check: extract_u32(&gv.data, 1024 + 64)?,
+            last_mount: extract_u32(&gv.data, 1024 + 44)?,
+            last_write: extract_u32(&gv.data, 1024 + 48)?,
 
+            soft_delete: false,
+            follow_symlinks: false,
             data: gv.data,
-        }
+        };
+        ext2.group_descriptors = ext2.read_group_descriptors()?;
+        Ok(ext2)
     }
 
-    fn info(&self) {
-        println!("{}\n
-Filesystem: EXT2\n
-INFO INODE
-Mida Inode: {}
-Num Inodes: {}
-Primer Inode: {}
-Inodes Grup: {}
-Inodes Lliures: {}\n
-INFO BLOC
-Mida Bloc: {}
-Blocs Reservats: {}
-Blocs Lliures: {}
-Total Blocs: {}
-Primer Bloc: {}
-Blocs grup: {}
-Frags grup: {}\n
-INFO VOLUM
-Nom volum: {}
-Ultima comprov: {}
-Ultim muntatge: {}
-Ultima escriptura: {}", INFO_HEADER,
-                 self.inode_size,
-                 self.inode_count,
-                 self.first_inode,
-                 self.inodes_x_group,
-                 self.free_inodes,
-                 self.block_size,
-                 self.rsvd_blocks,
-                 self.free_blocks,
-                 self.block_count,
-                 self.first_block,
-                 self.group_blocks_count,
-                 self.group_frags_count,
-                 self.volume_name,
-                 timestamp_to_date_time(self.last_check),
-                 timestamp_to_date_time(self.last_mount),
-                 timestamp_to_date_time(self.last_write),
-        )
-    }
-
-    fn find(&self) {
+    fn info(&self) -> VolumeInfo {
+        VolumeInfo::Ext2(Ext2Info {
+            inode_size: self.inode_size,
+            inode_count: self.inode_count,
+            first_inode: self.first_inode,
+            inodes_per_group: self.inodes_x_group,
+            free_inodes: self.free_inodes,
+            block_size: self.block_size,
+            reserved_blocks: self.rsvd_blocks,
+            free_blocks: self.free_blocks,
+            block_count: self.block_count,
+            first_block: self.first_block,
+            blocks_per_group: self.group_blocks_count,
+            frags_per_group: self.group_frags_count,
+            volume_name: self.volume_name.clone(),
+            group_count: self.group_descriptors.len() as u32,
+            feature_compat: self.feature_compat,
+            feature_incompat: self.feature_incompat,
+            feature_ro_compat: self.feature_ro_compat,
+            features: [
+                feature_names(self.feature_compat, &FEATURES_COMPAT),
+                feature_names(self.feature_incompat, &FEATURES_INCOMPAT),
+                feature_names(self.feature_ro_compat, &FEATURES_RO_COMPAT),
+            ].concat(),
+            last_check: self.last_check,
+            last_mount: self.last_mount,
+            last_write: self.last_write,
+        })
+    }
+
+    fn find(&self, file_name: &str) -> Result<Vec<FileEntry>, ShooterError> {
+        // Amb un path, només hi pot haver un resultat.
+        if file_name.contains('/') {
+            return match self.lookup(file_name) {
+                Ok(Some(entry)) if !self.read_inode(entry.inode as usize)?.is_dir() =>
+                    Ok(vec![self.file_entry(&entry.name, normalize_path(file_name), entry.inode as usize)?]),
+                Ok(_) | Err(ShooterError::FileNotFound) => Ok(vec![]),
+                Err(e) => Err(e),
+            };
+        }
 
         // Iniciem la cerca per el inode Root.
-        let found_result = self.find_in_inode(2, &self.file_name, 0);
+        let mut found = vec![];
+        self.find_in_dir(2, "/", file_name, &mut found, &mut HashSet::from([2]))?;
+        Ok(found)
+    }
 
-        if found_result.is_some() {
-            println!("{}{} bytes.", FILE_FOUND, found_result.unwrap().file_size);
-        } else {
-            println!("{}", FILE_NOT_FOUND);
+    fn list(&self, path: &str) -> Result<Vec<FileEntry>, ShooterError> {
+        let inode_num = self.resolve(path)?;
+
+        if !self.read_inode(inode_num)?.is_dir() {
+            let name = split_path(path).last().copied().unwrap_or_default();
+            return Ok(vec![self.file_entry(name, normalize_path(path), inode_num)?]);
         }
+
+        self.read_dir(inode_num)?
+            .iter()
+            .filter(|e| e.name != "." && e.name != "..")
+            .map(|e| self.file_entry(&e.name, join_path(&normalize_path(path), &e.name), e.inode as usize))
+            .collect()
     }
-    fn delete(&self) {
 
-        // Iniciem la cerca per el inode Root. Trobem el inode del fitxer.
-        let found_result = self.find_in_inode(2, &self.file_name, 0);
+    fn read_file(&self, path: &str, out: &mut dyn Write) -> Result<u64, ShooterError> {
+        let inode_num = self.resolve(path)?;
+        let inode = self.read_inode(inode_num)?;
+        match inode.file_type() {
+            FileType::Directory => return Err(ShooterError::IsADirectory),
+            FileType::Symlink => return Err(ShooterError::IsASymlink),
+            _ => {}
+        }
+
+        let (blocks, _) = self.inode_blocks(inode_num)?;
+        self.write_blocks(&blocks, inode.size, out)
+    }
 
-        if found_result.is_some() {
-            let result = found_result.unwrap();
+    fn delete(&mut self, path: &str) -> Result<DeleteReport, ShooterError> {
+        self.check_writable()?;
 
-            // Borrem l'inode!
-            let edited_file: Vec<u8> =self.delete_inode(result.file_inode, result.parent_directory_offset);
+        // Trobem l'entrada del fitxer dins del seu directori pare.
+        let entry = self.lookup(path)?.ok_or(ShooterError::IsADirectory)?;
+        if self.read_inode(entry.inode as usize)?.is_dir() {
+            return Err(ShooterError::IsADirectory);
+        }
 
-            if edited_file.len() != 0 {
-                fs::write(format!("{}{}", RESOURCES_PATH, self.vol_name), edited_file).expect("Unable to save new filesystem! Check program permissions!");
-                println!("{}{}{}", FILE_DELETED_1, self.file_name, FILE_DELETED_2);
-            } else {
-                println!("{}", FILE_NOT_FOUND);
+        // Borrem l'inode!
+        let (freed_blocks, freed_index_blocks, remaining_links) = self.delete_inode(entry.inode as usize, &entry)?;
+
+        Ok(DeleteReport {
+            name: normalize_path(path),
+            freed_blocks,
+            freed_index_blocks,
+            remaining_links,
+        })
+    }
+
+    fn remove_dir(&mut self, path: &str) -> Result<DeleteReport, ShooterError> {
+        self.check_writable()?;
+
+        let entry = self.lookup(path)?.ok_or(ShooterError::RootDirectory)?;
+        if !self.read_inode(entry.inode as usize)?.is_dir() {
+            return Err(ShooterError::NotADirectory);
+        }
+
+        // Un directori buit només conté . i .., i el .. ens diu quin és el pare.
+        let mut parent_inode = None;
+        for child in self.read_dir(entry.inode as usize)? {
+            match child.name.as_str() {
+                "." => {}
+                ".." => parent_inode = Some(child.inode as usize),
+                _ => return Err(ShooterError::DirectoryNotEmpty),
             }
+        }
+
+        let (freed_blocks, freed_index_blocks, _) = self.delete_inode(entry.inode as usize, &entry)?;
+
+        // El .. del directori esborrat era un enllaç al pare.
+        if let Some(parent_inode) = parent_inode {
+            self.add_subdir_link(parent_inode, -1)?;
+        }
+
+        Ok(DeleteReport {
+            name: normalize_path(path),
+            freed_blocks,
+            freed_index_blocks,
+            remaining_links: 0,
+        })
+    }
+
+    fn set_follow_symlinks(&mut self, follow: bool) -> Result<(), ShooterError> {
+        self.follow_symlinks = follow;
+        Ok(())
+    }
+
+    fn read_link(&self, path: &str) -> Result<String, ShooterError> {
+        let entry = self.lookup_path(path, false)?.ok_or(ShooterError::NotASymlink)?;
+        let inode = self.read_inode(entry.inode as usize)?;
+        if inode.file_type() != FileType::Symlink {
+            return Err(ShooterError::NotASymlink);
+        }
+        self.link_target(entry.inode as usize, &inode)
+    }
+
+    fn set_soft_delete(&mut self, soft: bool) -> Result<(), ShooterError> {
+        self.soft_delete = soft;
+        Ok(())
+    }
+
+    fn list_deleted(&self) -> Result<Vec<DeletedFile>, ShooterError> {
+        let mut deleted = vec![];
+        for inode_num in self.first_inode as usize..=self.inode_count as usize {
+            let inode = match self.deleted_inode(inode_num) {
+                Ok(inode) => inode,
+                Err(ShooterError::FileNotFound) => continue,
+                Err(e) => return Err(e),
+            };
+
+            // Si l'arbre de blocs ja està trepitjat, no sabem quins blocs tenia.
+            let (blocks, index_blocks) = self.inode_blocks(inode_num).unwrap_or_default();
+            let all_blocks: Vec<u32> = blocks.iter().map(|b| b.block).chain(index_blocks).collect();
+            let free_blocks = all_blocks.iter().filter(|&&block| !self.block_in_use(block).unwrap_or(true)).count();
+
+            deleted.push(DeletedFile {
+                inode: inode_num as u32,
+                file_type: inode.file_type(),
+                mode: inode.mode,
+                size: inode.size,
+                deleted: inode.dtime,
+                blocks: all_blocks.len() as u32,
+                free_blocks: free_blocks as u32,
+            });
+        }
+        Ok(deleted)
+    }
+
+    fn read_deleted(&self, inode: u32, out: &mut dyn Write) -> Result<u64, ShooterError> {
+        let deleted = self.deleted_inode(inode as usize)?;
+        if deleted.is_dir() {
+            return Err(ShooterError::IsADirectory);
+        }
+        let (blocks, _) = self.recoverable_blocks(inode as usize, &deleted)?;
+        self.write_blocks(&blocks, deleted.size, out)
+    }
+
+    fn undelete_inode(&mut self, inode: u32, path: &str) -> Result<FileEntry, ShooterError> {
+        self.check_writable()?;
+
+        let inode_num = inode as usize;
+        let deleted = self.deleted_inode(inode_num)?;
+        if deleted.is_dir() {
+            return Err(ShooterError::IsADirectory);
+        }
+        let (blocks, index_blocks) = self.recoverable_blocks(inode_num, &deleted)?;
+
+        let (parent_inode, name) = self.new_entry_parent(path)?;
+        self.add_dir_entry(parent_inode, name, inode, deleted.dir_entry_type())?;
+
+        // Tornem a marcar com a usats l'inode i tots els seus blocs.
+        for block in blocks.iter().map(|b| b.block).chain(index_blocks.iter().copied()) {
+            self.use_block(block)?;
+        }
+        self.use_inode(inode_num, false)?;
+
+        // i_dtime a 0, un enllaç (la nova entrada) i i_ctime actual.
+        let offset = self.compute_inode_offset(inode_num)?;
+        save_u32(&mut self.data, offset + 20, 0)?;
+        save_u16(&mut self.data, offset + 26, 1)?;
+        save_u32(&mut self.data, offset + 12, current_time())?;
+
+        self.file_entry(name, normalize_path(path), inode_num)
+    }
+
+    fn write_file(&mut self, path: &str, contents: &[u8]) -> Result<FileEntry, ShooterError> {
+        self.check_writable()?;
+
+        let (parent_inode, name) = self.new_entry_parent(path)?;
+        if name.len() > 255 {
+            return Err(ShooterError::NameTooLong);
+        }
+        let size = u32::try_from(contents.len()).map_err(|_| ShooterError::NoSpace)?;
+
+        // L'inode i les dades, a prop del directori pare.
+        let (group_num, _) = self.inode_group(parent_inode)?;
+        let inode_num = self.allocate_inode(group_num, false)?;
+        self.init_inode(inode_num, 0x8000 | 0o644, parent_inode)?;
+
+        let mut goal = self.group_first_block(group_num);
+        let mut blocks = 0;
+        for (logical, chunk) in contents.chunks(self.block_size as usize).enumerate() {
+            let block = self.allocate_block(&mut goal)?;
+            let block_offset = self.block_offset(block);
+            extract_slice_mut(&mut self.data, block_offset, chunk.len())?.copy_from_slice(chunk);
+            blocks += 1 + self.map_block(inode_num, logical as u32, block, &mut goal)?;
+        }
+        let offset = self.compute_inode_offset(inode_num)?;
+        save_u32(&mut self.data, offset + 4, size)?;
+        self.add_i_blocks(inode_num, blocks)?;
+
+        self.add_dir_entry(parent_inode, name, inode_num as u32, 1)?;
+        self.file_entry(name, normalize_path(path), inode_num)
+    }
+
+    fn create_dir(&mut self, path: &str) -> Result<FileEntry, ShooterError> {
+        self.check_writable()?;
+
+        let (parent_inode, name) = self.new_entry_parent(path)?;
+        if name.len() > 255 {
+            return Err(ShooterError::NameTooLong);
+        }
+
+        // Un directori té dos enllaços: l'entrada del pare i el seu propi ".".
+        let (group_num, _) = self.inode_group(parent_inode)?;
+        let inode_num = self.allocate_inode(group_num, true)?;
+        self.init_inode(inode_num, 0x4000 | 0o755, parent_inode)?;
+        let offset = self.compute_inode_offset(inode_num)?;
+        save_u16(&mut self.data, offset + 26, 2)?;
+
+        // Un sol bloc amb . i .., que ocupa la resta del bloc.
+        let mut goal = self.group_first_block(group_num);
+        let block = self.allocate_block(&mut goal)?;
+        let block_offset = self.block_offset(block);
+        self.write_dir_entry(block_offset, inode_num as u32, 12, ".", 2)?;
+        self.write_dir_entry(block_offset + 12, parent_inode as u32, (self.block_size - 12) as u16, "..", 2)?;
+        let index_blocks = self.map_block(inode_num, 0, block, &mut goal)?;
+        save_u32(&mut self.data, offset + 4, self.block_size)?;
+        self.add_i_blocks(inode_num, 1 + index_blocks)?;
+
+        self.add_dir_entry(parent_inode, name, inode_num as u32, 2)?;
+        // El .. és un enllaç més al pare.
+        self.add_subdir_link(parent_inode, 1)?;
+
+        self.file_entry(name, normalize_path(path), inode_num)
+    }
+
+    fn truncate(&mut self, path: &str, size: u32) -> Result<FileEntry, ShooterError> {
+        self.check_writable()?;
+
+        let entry = self.regular_file(path)?;
+        self.resize_inode(entry.inode as usize, size)?;
+        self.file_entry(&entry.name, normalize_path(path), entry.inode as usize)
+    }
+
+    fn append(&mut self, path: &str, contents: &[u8]) -> Result<FileEntry, ShooterError> {
+        self.check_writable()?;
+
+        let entry = self.regular_file(path)?;
+        let old_size = self.read_inode(entry.inode as usize)?.size;
+        let size = u32::try_from(old_size as u64 + contents.len() as u64).map_err(|_| ShooterError::NoSpace)?;
+        let blocks = self.resize_inode(entry.inode as usize, size)?;
+        self.write_at(&blocks, old_size, contents)?;
+        self.file_entry(&entry.name, normalize_path(path), entry.inode as usize)
+    }
+
+    fn rename(&mut self, src: &str, dst: &str) -> Result<FileEntry, ShooterError> {
+        self.check_writable()?;
+
+        let entry = self.lookup(src)?.ok_or(ShooterError::RootDirectory)?;
+        // . i .. no són entrades que es puguin moure.
+        if entry.name == "." || entry.name == ".." {
+            return Err(ShooterError::FileNotFound);
+        }
+        let src_components = split_path(src);
+        let src_parent = self.resolve(&src_components[..src_components.len() - 1].join("/"))?;
+        let inode = self.read_inode(entry.inode as usize)?;
+
+        // Si dst és un directori que ja existeix, hi movem src amb el mateix nom.
+        let dst_dir = match self.lookup(dst) {
+            Ok(found) => {
+                let dir = found.map_or(2, |e| e.inode as usize);
+                if !self.read_inode(dir)?.is_dir() {
+                    return Err(ShooterError::AlreadyExists);
+                }
+                Some(dir)
+            }
+            Err(ShooterError::FileNotFound) => None,
+            Err(e) => return Err(e),
+        };
+        let (dst_parent, name, dst_path) = match dst_dir {
+            Some(dir) => {
+                if self.read_dir(dir)?.iter().any(|e| e.name == entry.name) {
+                    return Err(ShooterError::AlreadyExists);
+                }
+                (dir, entry.name.clone(), join_path(&normalize_path(dst), &entry.name))
+            }
+            None => {
+                let (parent, name) = self.new_entry_parent(dst)?;
+                (parent, name.to_string(), normalize_path(dst))
+            }
+        };
+        if name.len() > 255 {
+            return Err(ShooterError::NameTooLong);
+        }
+        if inode.is_dir() && self.is_ancestor(entry.inode as usize, dst_parent)? {
+            return Err(ShooterError::MoveIntoItself);
+        }
+
+        // Dins del mateix directori, si el nom nou cap a l'entrada actual, només reescrivim el nom.
+        let file_type = inode.dir_entry_type();
+        let rec_len = extract_u16(&self.data, entry.offset + 4)?;
+        if dst_parent == src_parent && (8 + name.len() + 3) & !3 <= rec_len as usize {
+            self.write_dir_entry(entry.offset, entry.inode, rec_len, &name, file_type)?;
+            self.touch_dir(src_parent)?;
         } else {
-            println!("{}", FILE_NOT_FOUND);
+            self.remove_dir_entry(&entry)?;
+            self.touch_dir(src_parent)?;
+            self.add_dir_entry(dst_parent, &name, entry.inode, file_type)?;
+        }
+
+        // Un directori que canvia de pare: el seu .. passa a ser un enllaç del pare nou.
+        if inode.is_dir() && dst_parent != src_parent {
+            if let Some(dot_dot) = self.read_dir(entry.inode as usize)?.iter().find(|e| e.name == "..") {
+                save_u32(&mut self.data, dot_dot.offset, dst_parent as u32)?;
+            }
+            self.add_subdir_link(src_parent, -1)?;
+            self.add_subdir_link(dst_parent, 1)?;
         }
+
+        // i_ctime: ha canviat l'inode.
+        let offset = self.compute_inode_offset(entry.inode as usize)?;
+        save_u32(&mut self.data, offset + 12, current_time())?;
+
+        self.file_entry(&name, dst_path, entry.inode as usize)
+    }
+
+    fn update_backups(&mut self) -> Result<(), ShooterError> {
+        self.check_writable()?;
+
+        let superblock = extract_slice(&self.data, 1024, 1024)?.to_vec();
+        let table_offset = self.block_offset(self.first_block + 1);
+        let table_len = self.group_descriptors.len() * self.desc_size()?;
+        let group_table = extract_slice(&self.data, table_offset, table_len)?.to_vec();
+
+        for group_num in 1..self.group_descriptors.len() {
+            if !self.has_superblock_backup(group_num)? {
+                continue;
+            }
+
+            // La còpia és al primer bloc del grup, i la taula de descriptors al següent.
+            let group_start = self.first_block + (group_num as u32 * self.group_blocks_count);
+            let backup_offset = self.block_offset(group_start);
+            extract_slice_mut(&mut self.data, backup_offset, 1024)?.copy_from_slice(&superblock);
+            // s_block_group_nr: cada còpia sap a quin grup és.
+            save_u16(&mut self.data, backup_offset + 90, group_num as u16)?;
+
+            let backup_table_offset = self.block_offset(group_start + 1);
+            extract_slice_mut(&mut self.data, backup_table_offset, table_len)?.copy_from_slice(&group_table);
+        }
+        Ok(())
+    }
+
+    fn save(&self) -> Result<(), ShooterError> {
+        save_volume(&self.path, &self.data)
     }
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,423 @@
+use super::*;
+use std::io;
+
+// Inodes del volum de prova, tots al mateix grup.
+const INODES: u32 = 64;
+
+// Crea en memòria un volum ext2 (revisió 1, inodes de 128 bytes, filetype) amb un sol grup: superblock,
+// descriptors, bitmaps, taula d'inodes i el bloc del directori arrel, en aquest ordre. Com fa mke2fs, el grup
+// és de mida completa encara que el volum s'acabi abans.
+fn mkfs(block_size: u32, blocks_count: u32, incompat: u32) -> Ext2 {
+    let first_block = if block_size == 1024 { 1 } else { 0 };
+    let group_blocks = blocks_count - first_block;
+    assert!(group_blocks <= 8 * block_size);
+    let bs = block_size as usize;
+    let mut data = vec![0; blocks_count as usize * bs];
+
+    let gdt = first_block + 1;
+    let (block_bitmap, inode_bitmap, inode_table) = (gdt + 1, gdt + 2, gdt + 3);
+    let root_block = inode_table + INODES * 128 / block_size;
+    let used_blocks = root_block + 1 - first_block;
+    let used_inodes = 10;
+
+    let log_block_size = block_size.trailing_zeros() - 10;
+    let superblock = [
+        (0, INODES), (4, blocks_count), (12, group_blocks - used_blocks), (16, INODES - used_inodes),
+        (20, first_block), (24, log_block_size), (28, log_block_size), (32, 8 * block_size),
+        (36, 8 * block_size), (40, INODES), (76, 1), (84, 11), (96, FEATURE_INCOMPAT_FILETYPE | incompat),
+    ];
+    for (field, value) in superblock {
+        save_u32(&mut data, 1024 + field, value).unwrap();
+    }
+    save_u16(&mut data, 1024 + 56, 0xEF53).unwrap();
+    save_u16(&mut data, 1024 + 58, 1).unwrap();
+    save_u16(&mut data, 1024 + 88, 128).unwrap();
+
+    let gd = gdt as usize * bs;
+    save_u32(&mut data, gd, block_bitmap).unwrap();
+    save_u32(&mut data, gd + 4, inode_bitmap).unwrap();
+    save_u32(&mut data, gd + 8, inode_table).unwrap();
+    save_u16(&mut data, gd + 12, (group_blocks - used_blocks) as u16).unwrap();
+    save_u16(&mut data, gd + 14, (INODES - used_inodes) as u16).unwrap();
+    save_u16(&mut data, gd + 16, 1).unwrap();
+
+    // El que ja és en ús, i tot el que queda després del final del grup.
+    for bit in (0..used_blocks).chain(group_blocks..8 * block_size) {
+        set_bit(&mut data, block_bitmap as usize * bs + bit as usize / 8, (bit % 8) as u8).unwrap();
+    }
+    for bit in (0..used_inodes).chain(INODES..8 * block_size) {
+        set_bit(&mut data, inode_bitmap as usize * bs + bit as usize / 8, (bit % 8) as u8).unwrap();
+    }
+
+    // L'arrel és l'inode 2, amb . i .. al seu únic bloc.
+    let root = inode_table as usize * bs + 128;
+    save_u16(&mut data, root, 0x41ED).unwrap();
+    save_u32(&mut data, root + 4, block_size).unwrap();
+    save_u16(&mut data, root + 26, 2).unwrap();
+    save_u32(&mut data, root + 28, block_size / 512).unwrap();
+    save_u32(&mut data, root + 40, root_block).unwrap();
+    let dir = root_block as usize * bs;
+    save_u32(&mut data, dir, 2).unwrap();
+    save_u16(&mut data, dir + 4, 12).unwrap();
+    data[dir + 6..dir + 9].copy_from_slice(&[1, 2, b'.']);
+    save_u32(&mut data, dir + 12, 2).unwrap();
+    save_u16(&mut data, dir + 16, (block_size - 12) as u16).unwrap();
+    data[dir + 18..dir + 22].copy_from_slice(&[2, 2, b'.', b'.']);
+
+    Ext2::new(GenericVolume { data, path: None }).unwrap()
+}
+
+// Contingut de cada bloc dels fitxers esparsos: depèn de la posició, així es veu si un bloc surt on no toca.
+fn fill_byte(logical: u64) -> u8 {
+    (logical % 251) as u8 + 1
+}
+
+// Posa block a la posició logical de l'inode seguint l'esquema de punters d'ext2 (sense map_block), i
+// ocupa els blocs indirectes que calguin. Retorna quants n'ha ocupat.
+fn set_pointer(fs: &mut Ext2, inode_num: usize, logical: u64, block: u32, goal: &mut u32) -> u32 {
+    let p = fs.indirect_block_row_count as u64;
+    let (slot, mut rest, mut layers) = if logical < 12 {
+        (logical, 0, 0)
+    } else if logical < 12 + p {
+        (12, logical - 12, 1)
+    } else if logical < 12 + p + p * p {
+        (13, logical - 12 - p, 2)
+    } else {
+        (14, logical - 12 - p - p * p, 3)
+    };
+
+    let mut pointer = fs.compute_inode_offset(inode_num).unwrap() + 40 + 4 * slot as usize;
+    let mut new_index_blocks = 0;
+    while layers > 0 {
+        let mut next = extract_u32(&fs.data, pointer).unwrap();
+        if next == 0 {
+            next = fs.allocate_block(goal).unwrap();
+            save_u32(&mut fs.data, pointer, next).unwrap();
+            new_index_blocks += 1;
+        }
+        layers -= 1;
+        let span = p.pow(layers);
+        pointer = fs.block_offset(next) + 4 * (rest / span) as usize;
+        rest %= span;
+    }
+    save_u32(&mut fs.data, pointer, block).unwrap();
+    new_index_blocks
+}
+
+// Crea /name amb un bloc de dades a cada posició de logicals i forats a la resta. Acaba a la meitat del
+// bloc següent a l'últim, que també és un forat. Retorna la mida.
+fn build_sparse(fs: &mut Ext2, name: &str, logicals: &[u64]) -> u32 {
+    let inode_num = fs.allocate_inode(0, false).unwrap();
+    fs.init_inode(inode_num, 0x81A4, 2).unwrap();
+    fs.add_dir_entry(2, name, inode_num as u32, 1).unwrap();
+
+    let mut goal = fs.group_first_block(0);
+    let mut blocks = 0;
+    for &logical in logicals {
+        let block = fs.allocate_block(&mut goal).unwrap();
+        let block_offset = fs.block_offset(block);
+        extract_slice_mut(&mut fs.data, block_offset, fs.block_size as usize).unwrap().fill(fill_byte(logical));
+        blocks += 1 + set_pointer(fs, inode_num, logical, block, &mut goal);
+    }
+    fs.add_i_blocks(inode_num, blocks).unwrap();
+
+    let last = logicals.iter().max().unwrap();
+    let size = u32::try_from((last + 1) * fs.block_size as u64 + fs.block_size as u64 / 2).unwrap();
+    let offset = fs.compute_inode_offset(inode_num).unwrap();
+    save_u32(&mut fs.data, offset + 4, size).unwrap();
+    size
+}
+
+// Comprova que el que s'escriu és el contingut d'un fitxer fet per build_sparse.
+struct Expected<'a> {
+    block_size: u64,
+    logicals: &'a [u64],
+    pos: u64,
+    zeros: Vec<u8>,
+}
+
+impl<'a> Expected<'a> {
+    fn new(block_size: u32, logicals: &'a [u64]) -> Self {
+        Expected { block_size: block_size as u64, logicals, pos: 0, zeros: vec![0; block_size as usize] }
+    }
+}
+
+impl Write for Expected<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // write_blocks escriu bloc a bloc.
+        assert_eq!(self.pos % self.block_size, 0);
+        assert!(buf.len() as u64 <= self.block_size);
+        let logical = self.pos / self.block_size;
+        if self.logicals.contains(&logical) {
+            assert!(buf.iter().all(|&b| b == fill_byte(logical)), "bloc {} incorrecte", logical);
+        } else {
+            assert!(buf == &self.zeros[..buf.len()], "el forat {} no és a zero", logical);
+        }
+        self.pos += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn check_sparse(block_size: u32, logicals: &[u64]) {
+    let mut fs = mkfs(block_size, 256, 0);
+    let size = build_sparse(&mut fs, "sparse.bin", logicals);
+    let mut out = Expected::new(block_size, logicals);
+    assert_eq!(fs.read_file("/sparse.bin", &mut out).unwrap(), size as u64);
+    assert_eq!(out.pos, size as u64);
+}
+
+#[test]
+fn sparse_double_indirect() {
+    for block_size in [1024, 2048, 4096] {
+        let p = block_size as u64 / 4;
+        let double = 12 + p;
+        // Forats als directes, a tot el simple indirecte, dins d'un bloc indirecte i entre blocs indirectes.
+        check_sparse(block_size, &[2, double + 3, double + 2 * p + 5, double + 3 * p + 7]);
+    }
+}
+
+#[test]
+fn sparse_triple_indirect() {
+    // Amb blocs de 4K el triple indirecte comença a 4GiB, i i_size només en fa servir 32 bits.
+    for block_size in [1024, 2048] {
+        let p = block_size as u64 / 4;
+        let triple = 12 + p + p * p;
+        check_sparse(block_size, &[0, triple + 1, triple + p + 2, triple + p * p + 3]);
+    }
+}
+
+#[test]
+fn write_file_double_indirect() {
+    let mut fs = mkfs(1024, 512, 0);
+    let contents: Vec<u8> = (0..(12 + 256 + 10) * 1024 + 100).map(|k| (k % 253) as u8).collect();
+    fs.write_file("/big.bin", &contents).unwrap();
+    let mut out = vec![];
+    fs.read_file("/big.bin", &mut out).unwrap();
+    assert!(out == contents);
+}
+
+#[test]
+fn sparse_extents() {
+    let mut fs = mkfs(1024, 256, FEATURE_INCOMPAT_EXTENTS);
+    let inode_num = fs.allocate_inode(0, false).unwrap();
+    fs.init_inode(inode_num, 0x81A4, 2).unwrap();
+    fs.add_dir_entry(2, "ext.bin", inode_num as u32, 1).unwrap();
+
+    // Els blocs lògics 0-1 i 5, un forat entre mig, i els blocs 8-9 reservats però sense inicialitzar.
+    let mut goal = fs.group_first_block(0);
+    let blocks: Vec<u32> = (0..5).map(|_| fs.allocate_block(&mut goal).unwrap()).collect();
+    for (&block, logical) in blocks.iter().zip([0, 1, 5, 8, 9]) {
+        let block_offset = fs.block_offset(block);
+        // Un extent no inicialitzat pot tenir dades velles, que no s'han de veure.
+        let byte = if logical < 8 { fill_byte(logical) } else { 0xEE };
+        fs.data[block_offset..block_offset + 1024].fill(byte);
+    }
+
+    let offset = fs.compute_inode_offset(inode_num).unwrap();
+    save_u32(&mut fs.data, offset + 32, EXTENTS_FL).unwrap();
+    fs.init_extent_node(offset + 40, 0).unwrap();
+    save_u16(&mut fs.data, offset + 44, 4).unwrap();
+    for (k, &(logical, len, start)) in [(0, 2, blocks[0]), (5, 1, blocks[2]), (8, 32768 + 2, blocks[3])].iter().enumerate() {
+        let entry = offset + 40 + 12 + 12 * k;
+        save_u32(&mut fs.data, entry, logical).unwrap();
+        save_u16(&mut fs.data, entry + 4, len).unwrap();
+        save_u32(&mut fs.data, entry + 8, start).unwrap();
+    }
+    save_u16(&mut fs.data, offset + 42, 3).unwrap();
+    fs.add_i_blocks(inode_num, 5).unwrap();
+    save_u32(&mut fs.data, offset + 4, 10 * 1024 + 512).unwrap();
+
+    let mut out = Expected::new(1024, &[0, 1, 5]);
+    assert_eq!(fs.read_file("/ext.bin", &mut out).unwrap(), 10 * 1024 + 512);
+
+    // Els blocs no inicialitzats continuen sent de l'inode.
+    let (data_blocks, _) = fs.inode_blocks(inode_num).unwrap();
+    assert_eq!(data_blocks.len(), 5);
+    assert!(matches!(fs.truncate("/ext.bin", 1024), Err(ShooterError::Unsupported)));
+}
+
+// Bitmaps i comptadors de lliures (superblock i descriptor) del grup únic del volum de prova.
+fn allocation_state(fs: &Ext2) -> (Vec<u8>, Vec<u8>, [u32; 4]) {
+    let gd = fs.group(0).unwrap();
+    let bs = fs.block_size as usize;
+    let block_bitmap = fs.data[gd.block_bitmap as usize * bs..][..bs].to_vec();
+    let inode_bitmap = fs.data[gd.inode_bitmap as usize * bs..][..bs].to_vec();
+    let counts = [
+        extract_u32(&fs.data, 1024 + 12).unwrap(),
+        extract_u32(&fs.data, 1024 + 16).unwrap(),
+        extract_u16(&fs.data, gd.offset + 12).unwrap() as u32,
+        extract_u16(&fs.data, gd.offset + 14).unwrap() as u32,
+    ];
+    (block_bitmap, inode_bitmap, counts)
+}
+
+#[test]
+fn delete_sparse_frees_every_block() {
+    for block_size in [1024, 2048] {
+        let p = block_size as u64 / 4;
+        let triple = 12 + p + p * p;
+        let logicals = [3, 12 + p + 1, triple + p + 2, triple + p * p + 3];
+
+        let mut fs = mkfs(block_size, 256, 0);
+        let before = allocation_state(&fs);
+        build_sparse(&mut fs, "sparse.bin", &logicals);
+        assert_ne!(allocation_state(&fs), before);
+
+        let report = fs.delete("/sparse.bin").unwrap();
+        // El doble indirecte amb un simple, i el triple amb dos dobles que en tenen un cadascun.
+        assert_eq!((report.freed_blocks, report.freed_index_blocks), (4, 7));
+        assert_eq!(allocation_state(&fs), before);
+    }
+}
+
+// Com la passada 5 d'e2fsck: els bitmaps han de marcar exactament les metadades i els blocs i inodes en ús,
+// i els comptadors del superblock i del descriptor han de coincidir amb els bitmaps.
+fn check_counters(fs: &Ext2) {
+    let gd = fs.group(0).unwrap();
+    let metadata_end = gd.inode_table + INODES * fs.inode_size as u32 / fs.block_size;
+
+    let mut used_inodes = vec![];
+    let mut used_blocks: HashSet<u32> = (fs.first_block..metadata_end).collect();
+    let mut dirs = 0;
+    for inode_num in 1..=INODES as usize {
+        if !fs.inode_in_use(inode_num).unwrap() {
+            continue;
+        }
+        used_inodes.push(inode_num);
+        if inode_num != 2 && inode_num < fs.first_inode as usize {
+            continue;
+        }
+        dirs += fs.read_inode(inode_num).unwrap().is_dir() as u32;
+        let (data_blocks, index_blocks) = fs.inode_blocks(inode_num).unwrap();
+        for block in data_blocks.iter().map(|b| b.block).chain(index_blocks) {
+            assert!(used_blocks.insert(block), "el bloc {} és de dos llocs", block);
+        }
+    }
+    for block in fs.first_block..fs.block_count {
+        assert_eq!(fs.block_in_use(block).unwrap(), used_blocks.contains(&block), "bloc {}", block);
+    }
+
+    let free_blocks = fs.block_count - fs.first_block - used_blocks.len() as u32;
+    let free_inodes = INODES - used_inodes.len() as u32;
+    let (_, _, counts) = allocation_state(fs);
+    assert_eq!(counts, [free_blocks, free_inodes, free_blocks, free_inodes]);
+    assert_eq!((fs.free_blocks, fs.free_inodes), (free_blocks, free_inodes));
+    assert_eq!(extract_u16(&fs.data, gd.offset + 16).unwrap() as u32, dirs);
+}
+
+#[test]
+fn counters_after_put_delete_undelete() {
+    for block_size in [1024, 4096] {
+        let mut fs = mkfs(block_size, 512, 0);
+        check_counters(&fs);
+
+        // Buit, dins d'un sol bloc, només blocs directes, i amb doble indirecte.
+        let sizes = [0, 1, 12 * block_size as usize, (12 + 256 + 3) * 1024 + 7];
+        let contents: Vec<Vec<u8>> = sizes.iter().map(|&size| (0..size).map(|k| (k % 249) as u8).collect()).collect();
+        fs.create_dir("/dir").unwrap();
+        for (k, data) in contents.iter().enumerate() {
+            fs.write_file(&format!("/dir/{}.bin", k), data).unwrap();
+            check_counters(&fs);
+        }
+
+        fs.delete("/dir/1.bin").unwrap();
+        check_counters(&fs);
+
+        fs.set_soft_delete(true).unwrap();
+        fs.delete("/dir/3.bin").unwrap();
+        check_counters(&fs);
+
+        let deleted = fs.list_deleted().unwrap();
+        let inode = deleted.iter().find(|d| d.size == sizes[3] as u32).unwrap().inode;
+        fs.undelete_inode(inode, "/back.bin").unwrap();
+        check_counters(&fs);
+        let mut out = vec![];
+        fs.read_file("/back.bin", &mut out).unwrap();
+        assert!(out == contents[3]);
+
+        fs.delete("/dir/2.bin").unwrap();
+        fs.delete("/dir/0.bin").unwrap();
+        fs.remove_dir("/dir").unwrap();
+        check_counters(&fs);
+    }
+}
+
+#[test]
+fn directory_cycle_is_an_error() {
+    let mut fs = mkfs(1024, 256, 0);
+    let d = fs.create_dir("/d").unwrap().inode.unwrap();
+    let loop_dir = fs.create_dir("/d/loop").unwrap().inode.unwrap();
+    fs.write_file("/d/f.txt", b"f").unwrap();
+
+    // Com un `link d d/loop` de debugfs: l'entrada loop apunta al seu propi pare.
+    let entry = fs.read_dir(d as usize).unwrap().into_iter().find(|e| e.inode == loop_dir).unwrap();
+    save_u32(&mut fs.data, entry.offset, d).unwrap();
+
+    assert!(matches!(fs.find("f.txt"), Err(ShooterError::DirectoryCycle)));
+    assert!(matches!(fs.delete_recursive("/d"), Err(ShooterError::DirectoryCycle)));
+}
+
+#[test]
+fn failed_block_walk_leaves_nothing_behind() {
+    let mut fs = mkfs(1024, 256, 0);
+    let bad = fs.create_dir("/bad").unwrap().inode.unwrap() as usize;
+    fs.write_file("/bad/inner.txt", b"inner").unwrap();
+    fs.write_file("/ok.txt", b"ok").unwrap();
+
+    // El punter indirecte simple de /bad apunta fora del volum: el recorregut falla després de
+    // trobar el bloc directe.
+    let offset = fs.compute_inode_offset(bad).unwrap();
+    save_u32(&mut fs.data, offset + 40 + 4 * 12, 0x00FF_FFFF).unwrap();
+    assert!(fs.list("/bad").is_err());
+
+    let mut names: Vec<String> = fs.list("/").unwrap().into_iter().map(|e| e.name).collect();
+    names.sort();
+    assert_eq!(names, ["bad", "ok.txt"]);
+    assert_eq!(fs.delete("/ok.txt").unwrap().freed_blocks, 1);
+}
+
+#[test]
+fn rmdir_keeps_dir_nlink_count() {
+    let mut fs = mkfs(1024, 256, 0);
+    let parent = fs.create_dir("/p").unwrap().inode.unwrap() as usize;
+    fs.create_dir("/p/a").unwrap();
+    fs.create_dir("/p/b").unwrap();
+    assert_eq!(fs.read_inode(parent).unwrap().links_count, 4);
+    fs.remove_dir("/p/a").unwrap();
+    assert_eq!(fs.read_inode(parent).unwrap().links_count, 3);
+
+    // Amb dir_nlink, 1 vol dir que té massa subdirectoris per comptar-los.
+    let offset = fs.compute_inode_offset(parent).unwrap();
+    save_u16(&mut fs.data, offset + 26, 1).unwrap();
+    fs.remove_dir("/p/b").unwrap();
+    assert_eq!(fs.read_inode(parent).unwrap().links_count, 1);
+}
+
+#[test]
+fn huge_file_i_blocks() {
+    let mut fs = mkfs(1024, 256, 0);
+    save_u32(&mut fs.data, 1024 + 100, FEATURE_RO_COMPAT_HUGE_FILE).unwrap();
+    fs.feature_ro_compat = FEATURE_RO_COMPAT_HUGE_FILE;
+
+    let inode_num = fs.write_file("/h.bin", &[7; 3 * 1024]).unwrap().inode.unwrap() as usize;
+    let offset = fs.compute_inode_offset(inode_num).unwrap();
+    assert_eq!(fs.i_blocks(inode_num).unwrap(), 6);
+
+    // Amb EXT4_HUGE_FILE_FL, i_blocks compta blocs de 1K. En tornar-lo a escriure, passa a sectors.
+    save_u32(&mut fs.data, offset + 32, HUGE_FILE_FL).unwrap();
+    save_u32(&mut fs.data, offset + 28, 3).unwrap();
+    assert_eq!(fs.i_blocks(inode_num).unwrap(), 6);
+    fs.append("/h.bin", &[8; 1024]).unwrap();
+    assert_eq!(extract_u32(&fs.data, offset + 28).unwrap(), 8);
+    assert_eq!(extract_u32(&fs.data, offset + 32).unwrap() & HUGE_FILE_FL, 0);
+
+    // La part alta (l_i_blocks_hi) es conserva.
+    save_u16(&mut fs.data, offset + 116, 1).unwrap();
+    fs.append("/h.bin", &[9; 1024]).unwrap();
+    assert_eq!(fs.i_blocks(inode_num).unwrap(), (1 << 32) + 10);
+    assert_eq!(extract_u16(&fs.data, offset + 116).unwrap(), 1);
+}
@@ -1,28 +1,65 @@
 use core::fmt;
-use std::process::exit;
-use std::fs;
+use std::cmp::{min, Ordering};
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::io::Write;
+use crate::error::ShooterError;
 use crate::generics::*;
 use crate::utils::*;
-use std::cmp::min;
+use std::path::PathBuf;
 
-pub(crate) struct FAT16 {
-    file_name: String,
-    vol_name: String,
+pub struct FAT16 {
+    path: Option<PathBuf>,
     bpb_byts_per_sec: u16,
     bpb_sec_per_clus: u8,
     num_rsvd_sec: u16,
     bpb_num_fats: u8,
     bpb_root_ent_cnt: u16,
-    bpb_fatsz16: u16,
+    // BPB_FATSz16, o BPB_FATSz32 si el volum és FAT32.
+    fat_size: u32,
     bs_vol_lab: String,
     oem_name: String,
     data: Vec<u8>,
     root_dir_sectors: u16,
     first_data_sector: u32,
     data_sec: u32,
+    fat_type: FatType,
+    // Camps del BPB estès de FAT32. En FAT12 i FAT16 valen 0.
+    root_cluster: u32,
+    fs_info: u16,
+    bk_boot_sec: u16,
+    // Única FAT activa si el mirroring està desactivat (BPB_ExtFlags). None si totes són còpies.
+    active_fat: Option<u8>,
+    // Si és cert, delete conserva el cluster inicial, la mida i les dades per poder fer undelete.
+    soft_delete: bool,
 }
 
-enum FatType {
+/// Informació del BPB d'un volum FAT.
+#[derive(Debug, Clone)]
+pub struct Fat16Info {
+    pub fat_type: FatType,
+    pub oem_name: String,
+    pub bytes_per_sector: u16,
+    pub sectors_per_cluster: u8,
+    pub reserved_sectors: u16,
+    pub num_fats: u8,
+    /// Sempre 0 en FAT32, on el root directory és una cadena de clusters.
+    pub max_root_entries: u16,
+    pub sectors_per_fat: u32,
+    pub cluster_count: u32,
+    pub label: String,
+    /// Primer cluster del root directory. Només FAT32.
+    pub root_cluster: Option<u32>,
+    /// Sector del FSInfo. Només FAT32.
+    pub fs_info_sector: Option<u16>,
+    /// Sector de la còpia del boot sector. Només FAT32.
+    pub backup_boot_sector: Option<u16>,
+    /// Clusters lliures segons el FSInfo, si és vàlid. Només FAT32.
+    pub free_clusters: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FatType {
     FAT12,
     FAT16,
     FAT32,
@@ -38,272 +75,1322 @@ impl fmt::Display for FatType {
     }
 }
 
-impl FAT16 {
-    fn check_fat_type_is_fat16(&self) {
-        match self.get_fat_type() {
-            FatType::FAT12 => {
-                println!("{}", ERROR_FAT_12_FOUND);
-                exit(-1);
+// Una entrada de directori 8.3 ja decodificada, amb el seu nom llarg (LFN) si en té.
+struct DirEntry {
+    // Nom llarg si n'hi ha. Si no, el nom 8.3 en minúscules.
+    name: String,
+    short_name: String,
+    // Nom 8.3 tal com està guardat, amb espais.
+    raw_name: [u8; 11],
+    // Posició (en bytes) de l'entrada 8.3 i de les entrades LFN que la precedeixen.
+    offset: u32,
+    lfn_offsets: Vec<u32>,
+    attr: u8,
+    cluster: u32,
+    size: u32,
+    crt_time: u16,
+    crt_date: u16,
+    lst_acc_date: u16,
+    wrt_time: u16,
+    wrt_date: u16,
+}
+
+impl DirEntry {
+    fn parse(directory: &[u8], offset: u32, fat_type: FatType) -> Result<Self, ShooterError> {
+        let nom = extract_string(directory, 0, 8)?.replace(" ", "");
+        let extension = extract_string(directory, 8, 3)?.replace(" ", "");
+
+        let name = {
+            if extension.is_empty() {
+                nom.to_lowercase()
+            } else {
+                format!("{}.{}", nom, extension).to_lowercase()
             }
-            FatType::FAT16 => return,
-            FatType::FAT32 => {
-                println!("{}", ERROR_FAT_32_FOUND);
-                exit(-1);
+        };
+
+        // La part alta del cluster només existeix en FAT32.
+        let cluster_hi = match fat_type {
+            FatType::FAT32 => extract_u16(directory, 20)? as u32,
+            _ => 0,
+        };
+
+        let mut raw_name = [0u8; 11];
+        raw_name.copy_from_slice(&directory[0..11]);
+
+        Ok(DirEntry {
+            short_name: name.clone(),
+            name,
+            raw_name,
+            offset,
+            lfn_offsets: vec![],
+            attr: directory[11],
+            cluster: cluster_hi << 16 | extract_u16(directory, 26)? as u32,
+            size: extract_u32(directory, 28)?,
+            crt_time: extract_u16(directory, 14)?,
+            crt_date: extract_u16(directory, 16)?,
+            lst_acc_date: extract_u16(directory, 18)?,
+            wrt_time: extract_u16(directory, 22)?,
+            wrt_date: extract_u16(directory, 24)?,
+        })
+    }
+
+    fn is_dir(&self) -> bool {
+        self.attr & 0x10 == 0x10
+    }
+
+    // FAT no distingeix majúscules: comparem el nom llarg i també el nom 8.3.
+    fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        self.name.to_lowercase() == query || self.short_name == query
+    }
+
+    // Entrades que no s'han de mostrar: . i .. i l'etiqueta del volum.
+    fn is_hidden(&self) -> bool {
+        self.name == "." || self.name == ".." || self.attr & 0x08 == 0x08
+    }
+
+    fn to_file_entry(&self, path: String) -> FileEntry {
+        FileEntry {
+            name: self.name.clone(),
+            path,
+            file_type: if self.is_dir() { FileType::Directory } else { FileType::File },
+            size: self.size,
+            inode: None,
+            cluster: Some(self.cluster),
+            mode: None,
+            links: None,
+            created: dos_to_timestamp(self.crt_date, self.crt_time),
+            modified: dos_to_timestamp(self.wrt_date, self.wrt_time),
+            accessed: dos_to_timestamp(self.lst_acc_date, 0),
+            link_target: None,
+        }
+    }
+}
+
+// Entrades LFN llegides fins ara, pendents de l'entrada 8.3 que les segueix.
+// Van en ordre físic: la primera és la que té el tros final del nom (ordinal | 0x40).
+struct LongName {
+    checksum: u8,
+    next_ord: u8,
+    parts: Vec<[u16; 13]>,
+    offsets: Vec<u32>,
+}
+
+impl LongName {
+    // Afegeix una entrada LFN. Si trenca la seqüència, descarta el nom pendent.
+    fn push(long_name: &mut Option<LongName>, directory: &[u8], offset: u32) -> Result<(), ShooterError> {
+        let ord = directory[0];
+        let checksum = directory[13];
+        let part = lfn_part(directory)?;
+
+        // La primera entrada física porta el bit 0x40 i l'ordinal més gran.
+        if ord & 0x40 == 0x40 {
+            *long_name = Some(LongName {
+                checksum,
+                next_ord: (ord & 0x1F).wrapping_sub(1),
+                parts: vec![part],
+                offsets: vec![offset],
+            });
+            return Ok(());
+        }
+
+        *long_name = match long_name.take() {
+            Some(mut lfn) if lfn.next_ord == ord & 0x1F && lfn.next_ord != 0 && lfn.checksum == checksum => {
+                lfn.next_ord -= 1;
+                lfn.parts.push(part);
+                lfn.offsets.push(offset);
+                Some(lfn)
+            }
+            // Entrada òrfena o fora d'ordre: la ignorem.
+            _ => None,
+        };
+        Ok(())
+    }
+
+    // Retorna el nom si la seqüència és completa i correspon a l'entrada 8.3 `short_name`.
+    fn finish(self, short_name: &[u8]) -> Option<(String, Vec<u32>)> {
+        if self.next_ord != 0 || self.checksum != lfn_checksum(short_name) {
+            return None;
+        }
+
+        let name = lfn_name(self.parts.iter().rev())?;
+        Some((name, self.offsets))
+    }
+}
+
+// Els 13 caràcters UTF-16 d'una entrada LFN.
+fn lfn_part(directory: &[u8]) -> Result<[u16; 13], ShooterError> {
+    let mut part = [0u16; 13];
+    for (k, pos) in (1..11).step_by(2).chain((14..26).step_by(2)).chain((28..32).step_by(2)).enumerate() {
+        part[k] = extract_u16(directory, pos)?;
+    }
+    Ok(part)
+}
+
+// Ajunta els trossos d'un nom llarg, donats en l'ordre del nom (ordinal 1 primer).
+fn lfn_name<'a>(parts: impl Iterator<Item = &'a [u16; 13]>) -> Option<String> {
+    // El nom acaba amb 0x0000 i la resta s'omple amb 0xFFFF.
+    let name: Vec<u16> = parts
+        .flatten()
+        .copied()
+        .take_while(|&c| c != 0x0000)
+        .filter(|&c| c != 0xFFFF)
+        .collect();
+
+    let name = String::from_utf16(&name).ok()?;
+    if name.is_empty() {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+// Caràcters que pot tenir un nom 8.3, a part de lletres majúscules i xifres.
+const SHORT_NAME_SPECIAL_CHARS: &[u8] = b"!#$%&'()-@^_`{}~";
+
+// Converteix un nom a format 8.3 (11 bytes, amb espais). Retorna també si el nom 8.3 el representa
+// sense perdre res més que les minúscules; si no, cal afegir una cua ~N al nom 8.3.
+fn short_name_basis(name: &str) -> ([u8; 11], bool) {
+    let clean = |part: &str, len: usize| -> (Vec<u8>, bool) {
+        let mut exact = part.len() <= len;
+        let mut bytes = vec![];
+        for c in part.chars().filter(|&c| c != ' ' && c != '.') {
+            let c = c.to_ascii_uppercase();
+            if c.is_ascii_uppercase() || c.is_ascii_digit() || (c.is_ascii() && SHORT_NAME_SPECIAL_CHARS.contains(&(c as u8))) {
+                bytes.push(c as u8);
+            } else {
+                bytes.push(b'_');
+                exact = false;
             }
         }
+        exact &= bytes.len() == part.len();
+        bytes.truncate(len);
+        (bytes, exact)
+    };
+
+    // L'extensió és el que hi ha després de l'últim punt (si no és el primer caràcter).
+    let (base, extension) = match name.rfind('.') {
+        Some(dot) if dot > 0 => (&name[..dot], &name[dot + 1..]),
+        _ => (name, ""),
+    };
+    let (base_bytes, base_exact) = clean(base, 8);
+    let (ext_bytes, ext_exact) = clean(extension, 3);
+
+    let mut short_name = [b' '; 11];
+    short_name[..base_bytes.len()].copy_from_slice(&base_bytes);
+    short_name[8..8 + ext_bytes.len()].copy_from_slice(&ext_bytes);
+
+    (short_name, base_exact && ext_exact && !base_bytes.is_empty())
+}
+
+// Checksum del nom 8.3 que guarden totes les entrades LFN.
+fn lfn_checksum(short_name: &[u8]) -> u8 {
+    short_name.iter().fold(0u8, |sum, &c| ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(c))
+}
+
+// Una entrada 8.3 esborrada (0xE5) i les entrades LFN esborrades que la precedeixen.
+struct DeletedEntry {
+    // Entrada amb el primer caràcter, que s'ha perdut, substituït per '_'.
+    entry: DirEntry,
+    // Nom llarg i checksum de les seves entrades, si n'hi ha.
+    long_name: Option<(String, u8)>,
+}
+
+impl DeletedEntry {
+    // Diu si les entrades LFN són d'aquesta entrada 8.3 quan el primer caràcter és `first_char`.
+    fn lfn_matches(&self, first_char: u8) -> bool {
+        let mut raw_name = self.entry.raw_name;
+        raw_name[0] = first_char;
+        self.long_name.as_ref().is_some_and(|&(_, checksum)| lfn_checksum(&raw_name) == checksum)
     }
 
-    fn get_fat_type(&self) -> FatType {
-        return match self.data_sec / self.bpb_sec_per_clus as u32 {
+    // Primer caràcter que cal restaurar perquè l'entrada es digui `query`. Si coincideix el nom llarg,
+    // el deduïm del checksum. Si no, el dona l'usuari com a primera lletra de `query`.
+    fn first_char_for(&self, query: &str) -> Option<u8> {
+        let query = query.to_lowercase();
+        let guess = query.bytes().next().filter(u8::is_ascii_graphic)?.to_ascii_uppercase();
+
+        if let Some((long_name, _)) = &self.long_name {
+            if long_name.to_lowercase() == query {
+                return Some(guess)
+                    .filter(|&c| self.lfn_matches(c))
+                    .or_else(|| (0x21..0x7F).find(|&c| self.lfn_matches(c)));
+            }
+        }
+
+        let short_name = format!("{}{}", guess as char, &self.entry.short_name[1..]);
+        Some(guess).filter(|_| short_name.to_lowercase() == query)
+    }
+}
+
+enum ResolvedPath {
+    // Cluster del directori. None és el root directory.
+    Dir(Option<u32>),
+    File(DirEntry),
+}
+
+impl FAT16 {
+    // El tipus de FAT depèn només del nombre de clusters de la regió de dades.
+    fn get_fat_type(data_sec: u32, bpb_sec_per_clus: u8) -> FatType {
+        match data_sec / bpb_sec_per_clus as u32 {
             count_of_clusters if count_of_clusters < 4085 => FatType::FAT12,
             count_of_clusters if count_of_clusters < 65525 => FatType::FAT16,
             _ => FatType::FAT32
+        }
+    }
+
+    fn cluster_count(&self) -> u32 {
+        self.data_sec / self.bpb_sec_per_clus as u32
+    }
+
+    // Retorna l'inici i el final (en bytes) de la regió del root directory. En FAT32 és buida.
+    fn root_dir_region(&self) -> (u32, u32) {
+        let first_root_dir_sec_num = self.num_rsvd_sec as u32 + (self.bpb_num_fats as u32 * self.fat_size);
+        let first_root_dir_start = first_root_dir_sec_num * self.bpb_byts_per_sec as u32;
+        let first_root_dir_end = (self.root_dir_sectors as u32 * self.bpb_byts_per_sec as u32) + first_root_dir_start;
+        (first_root_dir_start, first_root_dir_end)
+    }
+
+    // Retorna el primer sector d'un cluster de la regió de dades. Els clusters 0 i 1 no existeixen.
+    fn first_sector_of_cluster(&self, cluster: u32) -> Result<u32, ShooterError> {
+        if cluster < 2 {
+            return Err(ShooterError::OutOfBounds { offset: cluster as usize, len: 0 });
+        }
+        Ok(((cluster - 2) * self.bpb_sec_per_clus as u32) + self.first_data_sector)
+    }
+
+    // Posició (en bytes) de l'entrada d'un cluster dins de la còpia `fat_num` de la FAT.
+    fn fat_entry_offset(&self, fat_num: u8, cluster: u32) -> usize {
+        let fat_start = (self.num_rsvd_sec as usize + fat_num as usize * self.fat_size as usize) * self.bpb_byts_per_sec as usize;
+        let entry_offset = match self.fat_type {
+            // Les entrades de FAT12 ocupen 12 bits: dues entrades cada 3 bytes.
+            FatType::FAT12 => cluster as usize + cluster as usize / 2,
+            FatType::FAT16 => cluster as usize * 2,
+            FatType::FAT32 => cluster as usize * 4,
         };
+        fat_start + entry_offset
     }
 
-    // Cerca un directori per trobar query_filename. Al trobar una carpeta, torna a executar la cerca a l'interior.
-    // Basicament és un DFS.
-    fn find_in_dir(&self, start: u32, end: u32, query_filename: &String) -> Option<u32> {
-        let mut i: u32 = start;
-        while i < end {
-            let directory = &self.data[i as usize..(i + 32) as usize];
+    // Llegeix de la FAT el cluster que segueix a `cluster`.
+    fn next_cluster(&self, cluster: u32) -> Result<u32, ShooterError> {
+        let pos = self.fat_entry_offset(self.active_fat.unwrap_or(0), cluster);
+        match self.fat_type {
+            FatType::FAT12 => {
+                let entry = extract_u16(&self.data, pos)?;
+                Ok(if cluster & 1 == 1 { entry >> 4 } else { entry & 0x0FFF } as u32)
+            }
+            FatType::FAT16 => Ok(extract_u16(&self.data, pos)? as u32),
+            // Els 4 bits alts estan reservats.
+            FatType::FAT32 => Ok(extract_u32(&self.data, pos)? & 0x0FFF_FFFF),
+        }
+    }
 
-            // No hi ha info en aquest bloc. Anem al seguent
-            if directory[0] == 0xE5 || directory[11] == 15 {
-                i += 32;
-                continue;
+    // Escriu l'entrada d'un cluster a la FAT actual i als backups.
+    fn set_fat_entry(&mut self, cluster: u32, value: u32) -> Result<(), ShooterError> {
+        let fats = match self.active_fat {
+            Some(fat_num) => fat_num..fat_num + 1,
+            None => 0..self.bpb_num_fats,
+        };
+
+        for r in fats {
+            let pos = self.fat_entry_offset(r, cluster);
+            match self.fat_type {
+                FatType::FAT12 => {
+                    // Cal conservar els 4 bits que pertanyen a l'entrada veïna.
+                    let old = extract_u16(&self.data, pos)?;
+                    let new = if cluster & 1 == 1 {
+                        (old & 0x000F) | ((value as u16) << 4)
+                    } else {
+                        (old & 0xF000) | (value as u16 & 0x0FFF)
+                    };
+                    save_u16(&mut self.data, pos, new)?;
+                }
+                FatType::FAT16 => save_u16(&mut self.data, pos, value as u16)?,
+                FatType::FAT32 => {
+                    let old = extract_u32(&self.data, pos)?;
+                    save_u32(&mut self.data, pos, (old & 0xF000_0000) | (value & 0x0FFF_FFFF))?;
+                }
             }
+        }
+        Ok(())
+    }
 
-            // No hi ha info en el bloc ni en en el seguents
-            if directory[0] == 0x00 {
-                break;
+    // Valor que marca l'últim cluster d'una cadena.
+    fn end_of_chain_mark(&self) -> u32 {
+        match self.fat_type {
+            FatType::FAT12 => 0x0FFF,
+            FatType::FAT16 => 0xFFFF,
+            FatType::FAT32 => 0x0FFF_FFFF,
+        }
+    }
+
+    // Els valors a partir de 0x?FF7 (cluster dolent i end of chain) tanquen la cadena.
+    fn is_end_of_chain(&self, cluster: u32) -> bool {
+        match self.fat_type {
+            FatType::FAT12 => cluster >= 0x0FF7,
+            FatType::FAT16 => cluster >= 0xFFF7,
+            FatType::FAT32 => cluster >= 0x0FFF_FFF7,
+        }
+    }
+
+    // Retorna les regions (inici, final en bytes) que ocupa un directori. None és el root directory.
+    fn dir_regions(&self, dir_cluster: Option<u32>) -> Result<Vec<(u32, u32)>, ShooterError> {
+        match dir_cluster {
+            // En FAT32 el root directory és una cadena de clusters com qualsevol altre directori.
+            None | Some(0) if self.fat_type == FatType::FAT32 => self.dir_regions(Some(self.root_cluster)),
+            None | Some(0) => Ok(vec![self.root_dir_region()]),
+            Some(cluster) => self.cluster_chain(cluster)?
+                .into_iter()
+                .map(|c| self.cluster_region(c))
+                .collect(),
+        }
+    }
+
+    // Retorna la regió (inici, final en bytes) d'un cluster de dades.
+    fn cluster_region(&self, cluster: u32) -> Result<(u32, u32), ShooterError> {
+        let start = self.first_sector_of_cluster(cluster)? * self.bpb_byts_per_sec as u32;
+        Ok((start, start + self.bpb_sec_per_clus as u32 * self.bpb_byts_per_sec as u32))
+    }
+
+    // Segueix la FAT des de first_cluster i retorna tots els clusters de la cadena.
+    fn cluster_chain(&self, first_cluster: u32) -> Result<Vec<u32>, ShooterError> {
+        let max_clusters = self.cluster_count();
+        let mut cluster_numbers = first_cluster;
+        let mut chain = vec![];
+
+        while !self.is_end_of_chain(cluster_numbers) {
+            // Una cadena mes llarga que el nombre de clusters té un cicle.
+            if chain.len() as u32 > max_clusters {
+                return Err(ShooterError::DirectoryCycle);
             }
+            chain.push(cluster_numbers);
+            cluster_numbers = self.next_cluster(cluster_numbers)?;
+        }
+        Ok(chain)
+    }
+
+    // Posició (en bytes) del sector FSInfo, si el volum en té un de vàlid.
+    fn fs_info_offset(&self) -> Option<usize> {
+        if self.fat_type != FatType::FAT32 || self.fs_info == 0 || self.fs_info == 0xFFFF {
+            return None;
+        }
+        let offset = self.fs_info as usize * self.bpb_byts_per_sec as usize;
+
+        // FSI_LeadSig i FSI_StrucSig
+        let lead_sig = extract_u32(&self.data, offset).ok()?;
+        let struc_sig = extract_u32(&self.data, offset + 484).ok()?;
+        if lead_sig == 0x4161_5252 && struc_sig == 0x6141_7272 {
+            Some(offset)
+        } else {
+            None
+        }
+    }
+
+    // FSI_Free_Count. 0xFFFFFFFF vol dir que no se sap.
+    fn fs_info_free_count(&self) -> Option<u32> {
+        let offset = self.fs_info_offset()?;
+        match extract_u32(&self.data, offset + 488).ok()? {
+            free if free <= self.cluster_count() => Some(free),
+            _ => None,
+        }
+    }
+
+    // Retorna count clusters lliures de la FAT, sense ocupar-los. NoSpace si no n'hi ha prou.
+    fn find_free_clusters(&self, count: u32) -> Result<Vec<u32>, ShooterError> {
+        let mut free = vec![];
+        let mut cluster = 2;
+        while (free.len() as u32) < count {
+            if cluster >= self.cluster_count() + 2 {
+                return Err(ShooterError::NoSpace);
+            }
+            if self.next_cluster(cluster)? == 0 {
+                free.push(cluster);
+            }
+            cluster += 1;
+        }
+        Ok(free)
+    }
+
+    // Encadena clusters a la FAT (l'últim amb end of chain) i els descompta del FSInfo.
+    fn allocate_chain(&mut self, clusters: &[u32]) -> Result<(), ShooterError> {
+        for (k, &cluster) in clusters.iter().enumerate() {
+            let next = clusters.get(k + 1).copied().unwrap_or_else(|| self.end_of_chain_mark());
+            self.set_fat_entry(cluster, next)?;
+        }
+        if let Some(free) = self.fs_info_free_count() {
+            self.set_fs_info_free_count(free.saturating_sub(clusters.len() as u32))?;
+        }
+        Ok(())
+    }
 
-            // Hem trobat un directori!
-            let nom = extract_string(directory, 0, 8).unwrap().replace(" ", "");
-            let extension = extract_string(directory, 8, 3).unwrap().replace(" ", "");
+    // Actualitza FSI_Free_Count, si el volum té un FSInfo vàlid.
+    fn set_fs_info_free_count(&mut self, free: u32) -> Result<(), ShooterError> {
+        match self.fs_info_offset() {
+            Some(offset) => save_u32(&mut self.data, offset + 488, free),
+            None => Ok(()),
+        }
+    }
+
+    // Llegeix totes les entrades d'un directori, ajuntant cada entrada 8.3 amb el seu nom llarg.
+    // Salta les esborrades.
+    fn read_dir(&self, dir_cluster: Option<u32>) -> Result<Vec<DirEntry>, ShooterError> {
+        let mut entries = vec![];
+        let mut long_name = None;
+        for (start, end) in self.dir_regions(dir_cluster)? {
+            let mut i = start;
+            while i < end {
+                let directory = extract_slice(&self.data, i as usize, 32)?;
+
+                // No hi ha info en el bloc ni en en el seguents
+                if directory[0] == 0x00 {
+                    return Ok(entries);
+                }
 
-            let filename = {
-                if extension == "" {
-                    nom.to_lowercase()
+                if directory[0] == 0xE5 {
+                    // No hi ha info en aquest bloc. Anem al seguent
+                    long_name = None;
+                } else if directory[11] & 0x3F == 0x0F {
+                    // Tros d'un nom llarg, el guardem fins trobar l'entrada 8.3.
+                    LongName::push(&mut long_name, directory, i)?;
                 } else {
-                    format!("{}.{}", nom, extension).to_lowercase()
+                    let mut entry = DirEntry::parse(directory, i, self.fat_type)?;
+                    if let Some((name, offsets)) = long_name.take().and_then(|lfn| lfn.finish(&directory[0..11])) {
+                        entry.name = name;
+                        entry.lfn_offsets = offsets;
+                    }
+                    entries.push(entry);
                 }
-            };
+                i += 32;
+            }
+        }
+        Ok(entries)
+    }
 
-            let attr = directory[11];
+    // Posició de cadascuna de les entrades (lliures o no) d'un directori, en ordre.
+    fn dir_slots(&self, dir_cluster: Option<u32>) -> Result<Vec<u32>, ShooterError> {
+        Ok(self.dir_regions(dir_cluster)?
+            .into_iter()
+            .flat_map(|(start, end)| (start..end).step_by(32))
+            .collect())
+    }
 
-            let mut cluster_numbers = (directory[27] as u16) << 8 | (directory[26] as u16);
-            let file_size = extract_u32(directory, 28);
+    // Troba count entrades lliures seguides (0x00 o 0xE5) dins d'un directori. Si no n'hi ha, l'allarga
+    // amb clusters nous. El root directory de FAT12 i FAT16 té una mida fixa i no es pot allargar.
+    // extra_clusters són els clusters que encara es necessitaran després, per comprovar l'espai abans.
+    fn reserve_dir_slots(&mut self, dir_cluster: Option<u32>, count: usize, extra_clusters: u32) -> Result<Vec<u32>, ShooterError> {
+        let slots = self.dir_slots(dir_cluster)?;
+        let is_free = |offset: u32| matches!(self.data.get(offset as usize), Some(0x00) | Some(0xE5));
 
-            // Directori es un subdirectori! Podem buscar a l'interior. Sempre i quan no sigui . o ..
-            if attr == 0x10 && directory[0] != 0x2e {
+        let mut run = 0;
+        for (k, &offset) in slots.iter().enumerate() {
+            run = if is_free(offset) { run + 1 } else { 0 };
+            if run == count {
+                return Ok(slots[k + 1 - count..=k].to_vec());
+            }
+        }
 
-                // Iterem per tots els clusters del directori trobat.
-                while{
-                    let first_sector_of_cluster = ((cluster_numbers - 2) as u32 * self.bpb_sec_per_clus as u32) + self.first_data_sector as u32;
-                    let new_dir_start = first_sector_of_cluster * self.bpb_byts_per_sec as u32;
-                    let new_dir_end = new_dir_start + (self.bpb_sec_per_clus as u16 * self.bpb_byts_per_sec) as u32;
+        let fixed_root = dir_cluster.is_none_or(|c| c == 0) && self.fat_type != FatType::FAT32;
+        if fixed_root {
+            return Err(ShooterError::NoSpace);
+        }
 
-                    // Explorem el seguent cluster
-                    let res = self.find_in_dir(new_dir_start, new_dir_end, query_filename);
-                    if res.is_some() {
-                        return res;
-                    }
+        // Clusters nous, buits, enganxats al final de la cadena del directori.
+        let slots_per_cluster = (self.bpb_sec_per_clus as usize * self.bpb_byts_per_sec as usize) / 32;
+        let new_count = (count - run).div_ceil(slots_per_cluster) as u32;
+        let new_clusters = self.find_free_clusters(new_count + extra_clusters)?;
+        let new_clusters = &new_clusters[..new_count as usize];
+
+        let first_cluster = match dir_cluster {
+            None | Some(0) => self.root_cluster,
+            Some(cluster) => cluster,
+        };
+        let last_cluster = *self.cluster_chain(first_cluster)?.last().ok_or(ShooterError::OutOfBounds { offset: first_cluster as usize, len: 0 })?;
+        self.allocate_chain(new_clusters)?;
+        self.set_fat_entry(last_cluster, new_clusters[0])?;
+        for &cluster in new_clusters {
+            let (start, end) = self.cluster_region(cluster)?;
+            extract_slice_mut(&mut self.data, start as usize, (end - start) as usize)?.fill(0);
+        }
+
+        let slots = self.dir_slots(dir_cluster)?;
+        Ok(slots[slots.len() - (run + new_count as usize * slots_per_cluster)..][..count].to_vec())
+    }
+
+    // Tria un nom 8.3 que no existeixi al directori: el nom tal qual si hi cap, o amb una cua ~N.
+    // Retorna també si cal un nom llarg, que guardem sempre que el nom 8.3 no és idèntic al nom.
+    fn unique_short_name(&self, name: &str, siblings: &[DirEntry]) -> Result<([u8; 11], bool), ShooterError> {
+        let (basis, fits) = short_name_basis(name);
+        let taken = |short_name: &[u8; 11]| siblings.iter().any(|e| e.raw_name == *short_name);
+        if fits && !taken(&basis) {
+            return Ok((basis, name != name.to_ascii_uppercase()));
+        }
 
-                    // Mirem el seguent cluster number de la fat.
-                    cluster_numbers = extract_u16(&self.data, ((self.num_rsvd_sec * self.bpb_byts_per_sec) as u32 + (cluster_numbers as u32 * 2)) as usize);
+        let base_len = basis[..8].iter().position(|&c| c == b' ').unwrap_or(8).max(1);
+        for n in 1..1_000_000 {
+            let tail = format!("~{}", n);
+            let mut short_name = basis;
+            let keep = base_len.min(8 - tail.len());
+            short_name[..8].fill(b' ');
+            short_name[..keep].copy_from_slice(&basis[..keep]);
+            if basis[0] == b' ' {
+                short_name[0] = b'_';
+            }
+            short_name[keep..keep + tail.len()].copy_from_slice(tail.as_bytes());
+            if !taken(&short_name) {
+                return Ok((short_name, true));
+            }
+        }
+        Err(ShooterError::NoSpace)
+    }
+
+    // Crea l'entrada de directori (amb nom llarg si cal) de path, que no pot existir, i li reserva
+    // cluster_count clusters encadenats. Retorna els clusters i el cluster del directori pare.
+    fn create_entry(&mut self, path: &str, attr: u8, size: u32, cluster_count: u32) -> Result<(Vec<u32>, Option<u32>), ShooterError> {
+        let (dir_cluster, name) = self.new_entry_parent(path)?;
+        let (short_name, long_name) = self.new_entry_name(dir_cluster, name, None)?;
+
+        // Primer les entrades de directori (que poden necessitar clusters) i després les dades,
+        // comprovant abans que hi ha espai per tot.
+        let slots = self.reserve_dir_slots(dir_cluster, long_name.len() + 1, cluster_count)?;
+        let clusters = self.find_free_clusters(cluster_count)?;
+        self.allocate_chain(&clusters)?;
+
+        self.write_lfn_entries(&slots[..long_name.len()], &long_name, &short_name)?;
+        let first_cluster = clusters.first().copied().unwrap_or(0);
+        self.write_short_entry(slots[long_name.len()], &short_name, attr, first_cluster, size)?;
+        Ok((clusters, dir_cluster))
+    }
+
+    // Directori pare i nom de l'últim component de path, per crear-hi una entrada.
+    fn new_entry_parent<'a>(&self, path: &'a str) -> Result<(Option<u32>, &'a str), ShooterError> {
+        let components = split_path(path);
+        let (name, parent) = components.split_last().ok_or(ShooterError::RootDirectory)?;
+        match self.resolve(&parent.join("/"))? {
+            ResolvedPath::Dir(dir_cluster) => Ok((dir_cluster, name)),
+            ResolvedPath::File(_) => Err(ShooterError::NotADirectory),
+        }
+    }
 
-                    // Si no hi han mes dades, sortim del loop.
-                    cluster_numbers < 0xFFF7
-                }{};
+    // Cluster que identifica un directori, tal com el guarda un ..: 0 pel root directory (també en FAT32).
+    fn dir_id(&self, dir_cluster: Option<u32>) -> u32 {
+        match dir_cluster {
+            Some(cluster) if cluster != self.root_cluster => cluster,
+            _ => 0,
+        }
+    }
 
-                // Aqui s'ha de mirar que no hi hagin més clusters a buscar.
-            } else if *query_filename == filename {
-                return Some(file_size);
+    // Diu si el directori dir_cluster és descendant o un dels seus ancestres, pujant pels .. fins al root.
+    fn is_ancestor(&self, dir_cluster: u32, descendant: Option<u32>) -> Result<bool, ShooterError> {
+        let mut descendant = self.dir_id(descendant);
+        // Un camí més llarg que el nombre de clusters vol dir que els .. fan un cicle.
+        for _ in 0..self.cluster_count() {
+            if descendant == dir_cluster {
+                return Ok(true);
+            }
+            if descendant == 0 {
+                return Ok(false);
             }
+            descendant = self.read_dir(Some(descendant))?
+                .iter()
+                .find(|e| e.name == "..")
+                .map(|e| e.cluster)
+                .ok_or(ShooterError::FileNotFound)?;
+        }
+        Err(ShooterError::DirectoryCycle)
+    }
 
-            i += 32;
+    // Tria el nom 8.3 i, si cal, el nom llarg d'una entrada nova del directori dir_cluster. El nom llarg
+    // ve partit en els trossos de cada entrada LFN (vec buit si no en cal). L'entrada a exclude (si
+    // s'està reanomenant) no compta com a existent.
+    fn new_entry_name(&self, dir_cluster: Option<u32>, name: &str, exclude: Option<u32>) -> Result<([u8; 11], Vec<[u16; 13]>), ShooterError> {
+        let siblings: Vec<DirEntry> = self.read_dir(dir_cluster)?
+            .into_iter()
+            .filter(|e| Some(e.offset) != exclude)
+            .collect();
+        if siblings.iter().any(|e| e.matches(name)) {
+            return Err(ShooterError::AlreadyExists);
         }
 
-        return None;
+        // Un nom llarg ocupa una entrada LFN per cada 13 caràcters UTF-16, fins a 255.
+        let (short_name, needs_lfn) = self.unique_short_name(name, &siblings)?;
+        let long_name: Vec<u16> = name.encode_utf16().collect();
+        if long_name.len() > 255 {
+            return Err(ShooterError::NameTooLong);
+        }
+        if !needs_lfn {
+            return Ok((short_name, vec![]));
+        }
+
+        // El nom acaba amb 0x0000 i la resta de l'últim tros s'omple amb 0xFFFF.
+        let parts = (0..long_name.len().div_ceil(13))
+            .map(|k| {
+                let mut part = [0xFFFFu16; 13];
+                for (j, c) in part.iter_mut().enumerate() {
+                    let pos = k * 13 + j;
+                    match pos.cmp(&long_name.len()) {
+                        Ordering::Less => *c = long_name[pos],
+                        Ordering::Equal => *c = 0x0000,
+                        Ordering::Greater => {}
+                    }
+                }
+                part
+            })
+            .collect();
+        Ok((short_name, parts))
     }
 
-    // Delete a file in root dir.
-    fn delete_in_dir(&self, start: u32, end: u32, query_filename: &String) -> Vec<u8> {
-        let mut i: u32 = start;
-        while i < end{
-            let directory = &self.data[i as usize..(i + 32) as usize];
+    // Escriu les entrades LFN d'un nom llarg a slots, que precedeixen l'entrada 8.3 short_name.
+    fn write_lfn_entries(&mut self, slots: &[u32], long_name: &[[u16; 13]], short_name: &[u8; 11]) -> Result<(), ShooterError> {
+        let lfn_count = long_name.len();
+        // Entrades LFN en ordre físic: primer la del tros final del nom (ordinal | 0x40).
+        let checksum = lfn_checksum(short_name);
+        for (k, &offset) in slots.iter().enumerate() {
+            let ord = (lfn_count - k) as u8;
+            let part = &long_name[ord as usize - 1];
 
-            // No hi ha info en aquest bloc. Anem al seguent
-            if directory[0] == 0xE5 || directory[11] == 15 {
-                i += 32;
-                continue;
+            let entry = extract_slice_mut(&mut self.data, offset as usize, 32)?;
+            entry.fill(0);
+            entry[0] = if k == 0 { ord | 0x40 } else { ord };
+            entry[11] = 0x0F;
+            entry[13] = checksum;
+            for (j, pos) in (1..11).step_by(2).chain((14..26).step_by(2)).chain((28..32).step_by(2)).enumerate() {
+                save_u16(&mut self.data, offset as usize + pos, part[j])?;
             }
+        }
+        Ok(())
+    }
 
-            // No hi ha info en el bloc ni en en el seguents
-            if directory[0] == 0x00 {
-                break;
+    // Escriu una entrada 8.3 amb la data actual.
+    fn write_short_entry(&mut self, offset: u32, short_name: &[u8; 11], attr: u8, first_cluster: u32, size: u32) -> Result<(), ShooterError> {
+        let offset = offset as usize;
+        let (date, time) = timestamp_to_dos(current_time());
+        let entry = extract_slice_mut(&mut self.data, offset, 32)?;
+        entry.fill(0);
+        entry[0..11].copy_from_slice(short_name);
+        entry[11] = attr;
+        save_u16(&mut self.data, offset + 14, time)?;
+        save_u16(&mut self.data, offset + 16, date)?;
+        save_u16(&mut self.data, offset + 18, date)?;
+        save_u16(&mut self.data, offset + 20, (first_cluster >> 16) as u16)?;
+        save_u16(&mut self.data, offset + 22, time)?;
+        save_u16(&mut self.data, offset + 24, date)?;
+        save_u16(&mut self.data, offset + 26, first_cluster as u16)?;
+        save_u32(&mut self.data, offset + 28, size)
+    }
+
+    // Canvia la mida del fitxer d'una entrada: escurça o allarga la seva cadena de clusters i actualitza la mida
+    // i les dates. El que passa a formar part del fitxer queda a zero. Retorna la cadena resultant.
+    fn resize_entry(&mut self, entry: &DirEntry, size: u32) -> Result<Vec<u32>, ShooterError> {
+        let cluster_size = self.bpb_sec_per_clus as u32 * self.bpb_byts_per_sec as u32;
+        let offset = entry.offset as usize;
+        let mut chain = if entry.cluster != 0 { self.cluster_chain(entry.cluster)? } else { vec![] };
+        let count = size.div_ceil(cluster_size) as usize;
+
+        if count < chain.len() {
+            for &cluster in &chain[count..] {
+                self.set_fat_entry(cluster, 0)?;
+            }
+            if let Some(free) = self.fs_info_free_count() {
+                self.set_fs_info_free_count(free + (chain.len() - count) as u32)?;
+            }
+            chain.truncate(count);
+            match chain.last() {
+                Some(&last) => self.set_fat_entry(last, self.end_of_chain_mark())?,
+                None => self.set_first_cluster(offset, 0)?,
+            }
+        } else if count > chain.len() {
+            let new_clusters = self.find_free_clusters((count - chain.len()) as u32)?;
+            self.allocate_chain(&new_clusters)?;
+            match chain.last() {
+                Some(&last) => self.set_fat_entry(last, new_clusters[0])?,
+                None => self.set_first_cluster(offset, new_clusters[0])?,
             }
+            chain.extend(new_clusters);
+        }
+
+        // Del final antic fins al final de la cadena: la resta de l'últim cluster i els clusters nous.
+        if size > entry.size {
+            for (k, &cluster) in chain.iter().enumerate() {
+                let from = entry.size.saturating_sub(k as u32 * cluster_size);
+                if from < cluster_size {
+                    let (start, end) = self.cluster_region(cluster)?;
+                    extract_slice_mut(&mut self.data, (start + from) as usize, (end - start - from) as usize)?.fill(0);
+                }
+            }
+        }
 
-            // Hem trobat un directori!
-            let nom = extract_string(directory, 0, 8).unwrap().replace(" ", "");
-            let extension = extract_string(directory, 8, 3).unwrap().replace(" ", "");
+        let (date, time) = timestamp_to_dos(current_time());
+        save_u16(&mut self.data, offset + 18, date)?;
+        save_u16(&mut self.data, offset + 22, time)?;
+        save_u16(&mut self.data, offset + 24, date)?;
+        save_u32(&mut self.data, offset + 28, size)?;
+        Ok(chain)
+    }
 
-            let filename = {
-                if extension == "" {
-                    nom.to_lowercase()
+    // Guarda el primer cluster d'una entrada 8.3. La part alta només existeix en FAT32.
+    fn set_first_cluster(&mut self, offset: usize, cluster: u32) -> Result<(), ShooterError> {
+        if self.fat_type == FatType::FAT32 {
+            save_u16(&mut self.data, offset + 20, (cluster >> 16) as u16)?;
+        }
+        save_u16(&mut self.data, offset + 26, cluster as u16)
+    }
+
+    // Escriu contents a partir del byte pos d'un fitxer amb la cadena chain, que ha de ser prou llarga.
+    fn write_at(&mut self, chain: &[u32], pos: u32, contents: &[u8]) -> Result<(), ShooterError> {
+        let cluster_size = self.bpb_sec_per_clus as u32 * self.bpb_byts_per_sec as u32;
+        let mut pos = pos;
+        let mut rest = contents;
+        while !rest.is_empty() {
+            let cluster = *chain.get((pos / cluster_size) as usize).ok_or(ShooterError::OutOfBounds { offset: pos as usize, len: rest.len() })?;
+            let within = pos % cluster_size;
+            let len = min((cluster_size - within) as usize, rest.len());
+            let (start, _) = self.cluster_region(cluster)?;
+            extract_slice_mut(&mut self.data, (start + within) as usize, len)?.copy_from_slice(&rest[..len]);
+            pos += len as u32;
+            rest = &rest[len..];
+        }
+        Ok(())
+    }
+
+    // Llegeix les entrades esborrades d'un directori. De les LFN ja no en sabem l'ordinal: agafem les
+    // que precedeixen l'entrada 8.3 amb el mateix checksum.
+    fn read_deleted(&self, dir_cluster: Option<u32>) -> Result<Vec<DeletedEntry>, ShooterError> {
+        let mut deleted = vec![];
+        let mut lfn_parts: Vec<(u32, u8, [u16; 13])> = vec![];
+        for (start, end) in self.dir_regions(dir_cluster)? {
+            let mut i = start;
+            while i < end {
+                let directory = extract_slice(&self.data, i as usize, 32)?;
+
+                if directory[0] == 0x00 {
+                    return Ok(deleted);
+                }
+
+                if directory[0] != 0xE5 {
+                    lfn_parts.clear();
+                } else if directory[11] & 0x3F == 0x0F {
+                    lfn_parts.push((i, directory[13], lfn_part(directory)?));
                 } else {
-                    format!("{}.{}", nom, extension).to_lowercase()
+                    let mut raw_entry = [0u8; 32];
+                    raw_entry.copy_from_slice(directory);
+                    raw_entry[0] = b'_';
+
+                    // Una entrada amb un nom il·legible no es pot recuperar pel nom.
+                    if let Ok(mut entry) = DirEntry::parse(&raw_entry, i, self.fat_type) {
+                        let long_name = match lfn_parts.last() {
+                            Some(&(_, checksum, _)) => {
+                                let parts: Vec<_> = lfn_parts.iter().rev().take_while(|p| p.1 == checksum).collect();
+                                entry.lfn_offsets = parts.iter().rev().map(|p| p.0).collect();
+                                lfn_name(parts.iter().map(|p| &p.2)).map(|name| (name, checksum))
+                            }
+                            None => None,
+                        };
+                        deleted.push(DeletedEntry { entry, long_name });
+                    }
+                    lfn_parts.clear();
                 }
-            };
+                i += 32;
+            }
+        }
+        Ok(deleted)
+    }
+
+    // Troba el directori o fitxer corresponent a un path.
+    fn resolve(&self, path: &str) -> Result<ResolvedPath, ShooterError> {
+        match self.lookup(path)? {
+            None => Ok(ResolvedPath::Dir(None)),
+            Some(entry) if entry.is_dir() => Ok(ResolvedPath::Dir(Some(entry.cluster))),
+            Some(entry) => Ok(ResolvedPath::File(entry)),
+        }
+    }
+
+    // Troba l'entrada de directori de l'últim component del path. None si el path és el root.
+    fn lookup(&self, path: &str) -> Result<Option<DirEntry>, ShooterError> {
+        let mut found: Option<DirEntry> = None;
 
-            let attr = directory[11];
-            let mut cluster_numbers = (directory[27] as u16) << 8 | (directory[26] as u16);
+        for component in split_path(path) {
+            // Tots els components menys l'últim han de ser directoris.
+            let dir_cluster = match &found {
+                None => None,
+                Some(entry) if entry.is_dir() => Some(entry.cluster),
+                Some(_) => return Err(ShooterError::FileNotFound),
+            };
 
-            let file_size = extract_u32(directory, 28);
+            found = Some(self.read_dir(dir_cluster)?
+                .into_iter()
+                .find(|e| e.matches(component))
+                .ok_or(ShooterError::FileNotFound)?);
+        }
+        Ok(found)
+    }
 
-            // Hem trobat el fitxer en el root. Si no es carpeta, el borrem.
-            if *query_filename == filename && !(attr == 0x10 && directory[0] != 0x2e){
-                let mut new_data = self.data.to_vec();
+    // Cerca recursivament tots els fitxers anomenats query_filename a partir de dir_cluster.
+    // Basicament és un DFS. visited té els directoris ja recorreguts (amb dir_id): si una entrada en
+    // torna a apuntar un, l'arbre té un cicle.
+    fn find_in_dir(&self, dir_cluster: Option<u32>, dir_path: &str, query_filename: &str, found: &mut Vec<FileEntry>, visited: &mut HashSet<u32>) -> Result<(), ShooterError> {
+        for entry in self.read_dir(dir_cluster)?.iter().filter(|e| !e.is_hidden()) {
+            let path = join_path(dir_path, &entry.name);
 
-                // Posem e5 en la directory entry.
-                new_data[i as usize] = 0xE5;
+            // Directori es un subdirectori! Podem buscar a l'interior.
+            if entry.is_dir() {
+                if !visited.insert(self.dir_id(Some(entry.cluster))) {
+                    return Err(ShooterError::DirectoryCycle);
+                }
+                self.find_in_dir(Some(entry.cluster), &path, query_filename, found, visited)?;
+            } else if entry.matches(query_filename) {
+                found.push(entry.to_file_entry(path));
+            }
+        }
+        Ok(())
+    }
 
-                save_u16(&mut new_data, (i + 26) as usize, 0);
-                save_u32(&mut new_data, (i + 28) as usize, 0);
+    // Esborra el fitxer d'una entrada de directori: marca amb 0xE5 l'entrada 8.3 i totes les LFN,
+    // i allibera la seva cadena de clusters. path és el de l'entrada, pel report.
+    fn delete_entry(&mut self, entry: &DirEntry, path: &str) -> Result<DeleteReport, ShooterError> {
+        let mut freed_blocks = 0;
+        let i = entry.offset;
 
-                // Si el fitxer és pler, invalidem el contingut
-                if file_size != 0 {
+        // Posem e5 en la directory entry i en les del nom llarg.
+        for &lfn_offset in &entry.lfn_offsets {
+            self.data[lfn_offset as usize] = 0xE5;
+        }
+        self.data[i as usize] = 0xE5;
 
-                    // Iterem per tots els clusters del directori trobat.
-                    while{
-                        let first_sector_of_cluster = ((cluster_numbers - 2) as u32 * self.bpb_sec_per_clus as u32) + self.first_data_sector as u32;
-                        let file_start = first_sector_of_cluster * self.bpb_byts_per_sec as u32;
-                        let file_end = file_start + min(file_size, (self.bpb_sec_per_clus as u16 * self.bpb_byts_per_sec) as u32);
+        // En mode soft, el cluster inicial i la mida es queden per poder fer undelete. Si no, el cluster
+        // inicial passa a ser una marca d'end of chain, que distingeix l'entrada d'un fitxer buit.
+        if !self.soft_delete {
+            let mark = self.end_of_chain_mark();
+            self.set_first_cluster(i as usize, mark)?;
+            save_u32(&mut self.data, (i + 28) as usize, 0)?;
+        }
 
-                        // Borra les dades a zero
-                        for k in file_start as usize .. (file_end) as usize {
-                            new_data[k] = 0;
-                        }
+        // Els directoris no tenen mida: n'esborrem els clusters sencers.
+        let cluster_size = self.bpb_sec_per_clus as u32 * self.bpb_byts_per_sec as u32;
+        let file_size = if entry.is_dir() { cluster_size } else { entry.size };
 
-                        // Mirem el seguent cluster number de la fat.
-                        let old_fat_pos = ((self.num_rsvd_sec * self.bpb_byts_per_sec) as u32 + (cluster_numbers as u32 * 2)) as usize;
-                        cluster_numbers = extract_u16(&self.data, old_fat_pos);
+        // Si el fitxer és pler, invalidem el contingut
+        if file_size != 0 && entry.cluster != 0 {
 
+            // Iterem per tots els clusters del directori trobat.
+            for cluster in self.cluster_chain(entry.cluster)? {
+                let file_start = self.first_sector_of_cluster(cluster)? * self.bpb_byts_per_sec as u32;
+                let file_end = file_start + min(file_size, cluster_size);
 
-                        // Borra el registre del FAT actual i dels backups.
-                        for r in 0..self.bpb_num_fats {
-                            save_u16(&mut new_data, old_fat_pos + r as usize * (self.bpb_fatsz16 as usize * self.bpb_byts_per_sec as usize), 0);
-                        }
-                        // Si no hi han mes dades, sortim del loop.
-                        cluster_numbers < 0xFFF7
-                    }{};
+                // Borra les dades a zero
+                if !self.soft_delete {
+                    extract_slice_mut(&mut self.data, file_start as usize, (file_end - file_start) as usize)?.fill(0);
                 }
 
-                return new_data;
+                // Borra el registre del FAT actual i dels backups.
+                self.set_fat_entry(cluster, 0)?;
+                freed_blocks += 1;
             }
-            i += 32;
         }
 
-        return vec![];
+        // En FAT32, el FSInfo porta el compte de clusters lliures.
+        if let Some(free) = self.fs_info_free_count() {
+            self.set_fs_info_free_count(free + freed_blocks)?;
+        }
+
+        Ok(DeleteReport {
+            name: normalize_path(path),
+            freed_blocks,
+            freed_index_blocks: 0,
+            remaining_links: 0,
+        })
     }
 }
 
 impl Filesystem for FAT16 {
-    fn new(gv: GenericVolume) -> Self {
-        let bpb_root_ent_cnt = extract_u16(&gv.data, 17);
-        let bpb_byts_per_sec = extract_u16(&gv.data, 11);
-        let num_rsvd_sec = extract_u16(&gv.data, 14);
+    fn new(gv: GenericVolume) -> Result<Self, ShooterError> {
+        let bpb_root_ent_cnt = extract_u16(&gv.data, 17)?;
+        let bpb_byts_per_sec = extract_u16(&gv.data, 11)?;
+        let bpb_sec_per_clus = gv.data[13];
+        let num_rsvd_sec = extract_u16(&gv.data, 14)?;
         let bpb_num_fats = gv.data[16];
-        let bpb_fatsz16 = extract_u16(&gv.data, 22);
+
+        // En FAT32 BPB_FATSz16 val 0 i la mida és a BPB_FATSz32.
+        let fat_size = match extract_u16(&gv.data, 22)? {
+            0 => extract_u32(&gv.data, 36)?,
+            fatsz16 => fatsz16 as u32,
+        };
+
+        // Sense aquests camps no podem calcular res del volum.
+        if bpb_byts_per_sec == 0 || bpb_sec_per_clus == 0 {
+            return Err(ShooterError::UnrecognizedFormat);
+        }
 
         // Calcul del nombre de sectors que ocupa el root directory.
-        let root_dir_sectors = ((bpb_root_ent_cnt * 32) + (bpb_byts_per_sec - 1)) / bpb_byts_per_sec;
+        let root_dir_sectors = (bpb_root_ent_cnt as u32 * 32).div_ceil(bpb_byts_per_sec as u32) as u16;
 
         // Start of data sector
-        let first_data_sector = num_rsvd_sec as u32 + (bpb_num_fats as u32 * bpb_fatsz16 as u32) + root_dir_sectors as u32;
+        let first_data_sector = (num_rsvd_sec as u32)
+            .checked_add(bpb_num_fats as u32 * fat_size)
+            .and_then(|s| s.checked_add(root_dir_sectors as u32))
+            .ok_or(ShooterError::UnrecognizedFormat)?;
 
-        let bpb_tot_sec16 = extract_u16(&gv.data, 19);
+        let bpb_tot_sec16 = extract_u16(&gv.data, 19)?;
 
         let bpb_tot_sec = if bpb_tot_sec16 == 0 {
-            extract_u32(&gv.data, 32)
+            extract_u32(&gv.data, 32)?
         } else {
             bpb_tot_sec16 as u32
         };
 
+        // El volum ha de contenir tots els sectors que declara el BPB.
+        let expected = bpb_tot_sec as usize * bpb_byts_per_sec as usize;
+        if gv.data.len() < expected {
+            return Err(ShooterError::TruncatedImage { expected, found: gv.data.len() });
+        }
+
         // Count of sectors in data region
-        let data_sec: u32 = bpb_tot_sec - first_data_sector;
+        let data_sec: u32 = bpb_tot_sec.checked_sub(first_data_sector).ok_or(ShooterError::UnrecognizedFormat)?;
+        let fat_type = Self::get_fat_type(data_sec, bpb_sec_per_clus);
+
+        let (root_cluster, fs_info, bk_boot_sec, active_fat, label_offset) = match fat_type {
+            FatType::FAT32 => {
+                // BPB_FSVer: només coneixem la versió 0.0.
+                if extract_u16(&gv.data, 42)? != 0 {
+                    return Err(ShooterError::UnsupportedFat(fat_type));
+                }
+
+                // BPB_ExtFlags: si el bit 7 està actiu, només la FAT dels bits 0-3 és vàlida.
+                let ext_flags = extract_u16(&gv.data, 40)?;
+                let active_fat = Some((ext_flags & 0x0F) as u8)
+                    .filter(|&f| ext_flags & 0x80 == 0x80 && f < bpb_num_fats);
+
+                // BPB_RootClus ha de ser un cluster de dades: els clusters vàlids van de 2 a cluster_count + 1.
+                let root_cluster = extract_u32(&gv.data, 44)?;
+                if root_cluster < 2 || root_cluster >= data_sec / bpb_sec_per_clus as u32 + 2 {
+                    return Err(ShooterError::UnrecognizedFormat);
+                }
 
-        let obj = FAT16 {
-            vol_name:gv.vol_name,
-            file_name: gv.file_name,
+                (root_cluster, extract_u16(&gv.data, 48)?, extract_u16(&gv.data, 50)?, active_fat, 71)
+            }
+            _ => (0, 0, 0, None, 43),
+        };
+
+        Ok(FAT16 {
+            path: gv.path,
             bpb_byts_per_sec,
-            bpb_sec_per_clus: gv.data[13],
+            bpb_sec_per_clus,
             num_rsvd_sec,
             bpb_num_fats,
             bpb_root_ent_cnt,
-            bpb_fatsz16,
-            bs_vol_lab: extract_string(&gv.data, 43, 11).unwrap().parse().unwrap(),
-            oem_name: extract_string(&gv.data, 3, 8).unwrap().parse().unwrap(),
+            fat_size,
+            bs_vol_lab: extract_string(&gv.data, label_offset, 11)?.to_string(),
+            oem_name: extract_string(&gv.data, 3, 8)?.to_string(),
             data: gv.data,
             root_dir_sectors,
             first_data_sector,
             data_sec,
+            fat_type,
+            root_cluster,
+            fs_info,
+            bk_boot_sec,
+            active_fat,
+            soft_delete: false,
+        })
+    }
+
+    fn info(&self) -> VolumeInfo {
+        let fat32 = self.fat_type == FatType::FAT32;
+        VolumeInfo::Fat16(Fat16Info {
+            fat_type: self.fat_type,
+            oem_name: self.oem_name.clone(),
+            bytes_per_sector: self.bpb_byts_per_sec,
+            sectors_per_cluster: self.bpb_sec_per_clus,
+            reserved_sectors: self.num_rsvd_sec,
+            num_fats: self.bpb_num_fats,
+            max_root_entries: self.bpb_root_ent_cnt,
+            sectors_per_fat: self.fat_size,
+            cluster_count: self.cluster_count(),
+            label: self.bs_vol_lab.clone(),
+            root_cluster: Some(self.root_cluster).filter(|_| fat32),
+            fs_info_sector: Some(self.fs_info).filter(|_| fat32),
+            backup_boot_sector: Some(self.bk_boot_sec).filter(|_| fat32),
+            free_clusters: self.fs_info_free_count(),
+        })
+    }
+
+    fn find(&self, file_name: &str) -> Result<Vec<FileEntry>, ShooterError> {
+        // Amb un path, només hi pot haver un resultat.
+        if file_name.contains('/') {
+            return match self.resolve(file_name) {
+                Ok(ResolvedPath::File(entry)) => Ok(vec![entry.to_file_entry(normalize_path(file_name))]),
+                Ok(ResolvedPath::Dir(_)) | Err(ShooterError::FileNotFound) => Ok(vec![]),
+                Err(e) => Err(e),
+            };
+        }
 
+        let mut found = vec![];
+        self.find_in_dir(None, "/", &file_name.to_lowercase(), &mut found, &mut HashSet::from([0]))?;
+        Ok(found)
+    }
+
+    fn list(&self, path: &str) -> Result<Vec<FileEntry>, ShooterError> {
+        match self.resolve(path)? {
+            ResolvedPath::File(entry) => Ok(vec![entry.to_file_entry(normalize_path(path))]),
+            ResolvedPath::Dir(dir_cluster) => Ok(self.read_dir(dir_cluster)?
+                .iter()
+                .filter(|e| !e.is_hidden())
+                .map(|e| e.to_file_entry(join_path(&normalize_path(path), &e.name)))
+                .collect()),
+        }
+    }
+
+    fn read_file(&self, path: &str, out: &mut dyn Write) -> Result<u64, ShooterError> {
+        let entry = match self.resolve(path)? {
+            ResolvedPath::File(entry) => entry,
+            ResolvedPath::Dir(_) => return Err(ShooterError::IsADirectory),
         };
-        obj.check_fat_type_is_fat16();
-        obj
-    }
-
-    fn info(&self) {
-        println!("{}\n
-Filesystem: {}
-System Name: {}
-Mida del sector: {}
-Sectors Per Cluster: {}
-Sectors reservats: {}
-Número de FATs: {}
-MaxRootEntries: {}
-Sectors per FAT: {}
-Label: {}",
-                 INFO_HEADER,
-                 self.get_fat_type(),
-                 self.oem_name,
-                 self.bpb_byts_per_sec,
-                 self.bpb_sec_per_clus,
-                 self.num_rsvd_sec,
-                 self.bpb_num_fats,
-                 self.bpb_root_ent_cnt,
-                 self.bpb_fatsz16,
-                 self.bs_vol_lab)
-    }
-
-    fn find(&self) {
-        let first_root_dir_sec_num = self.num_rsvd_sec as u32 + (self.bpb_num_fats as u32 * self.bpb_fatsz16 as u32);
-        let first_root_dir_start = first_root_dir_sec_num * self.bpb_byts_per_sec as u32;
-        let first_root_dir_end = (self.root_dir_sectors as u32 * self.bpb_byts_per_sec as u32) + first_root_dir_start;
 
-        let file_size = self.find_in_dir(first_root_dir_start, first_root_dir_end, &self.file_name);
-        if file_size.is_some() {
-            println!("{}{} bytes.", FILE_FOUND, file_size.unwrap());
-        } else {
-            println!("{}", FILE_NOT_FOUND);
+        // Un fitxer buit no té cap cluster assignat.
+        if entry.size == 0 {
+            return Ok(0);
+        }
+
+        // Si la cadena s'acaba abans de la mida, el fitxer està malament: no en copiem només un tros.
+        let chain = self.cluster_chain(entry.cluster)?;
+        let cluster_size = self.bpb_sec_per_clus as u32 * self.bpb_byts_per_sec as u32;
+        let available = chain.len() as u64 * cluster_size as u64;
+        if available < entry.size as u64 {
+            return Err(ShooterError::OutOfBounds { offset: available as usize, len: (entry.size as u64 - available) as usize });
         }
+
+        // Només copiem fins a la mida del fitxer, no la resta de l'últim cluster.
+        let mut remaining = entry.size;
+        for cluster in chain {
+            if remaining == 0 {
+                break;
+            }
+            let (start, end) = self.cluster_region(cluster)?;
+            let len = min(remaining, end - start);
+            out.write_all(extract_slice(&self.data, start as usize, len as usize)?).map_err(ShooterError::WriteFailed)?;
+            remaining -= len;
+        }
+        Ok(entry.size as u64)
     }
 
-    fn delete(&self) {
-        let first_root_dir_sec_num = self.num_rsvd_sec as u32 + (self.bpb_num_fats as u32 * self.bpb_fatsz16 as u32);
-        let first_root_dir_start = first_root_dir_sec_num * self.bpb_byts_per_sec as u32;
-        let first_root_dir_end = (self.root_dir_sectors as u32 * self.bpb_byts_per_sec as u32) + first_root_dir_start;
+    fn delete(&mut self, path: &str) -> Result<DeleteReport, ShooterError> {
+        // Trobem el fitxer seguint la cadena de clusters de cada directori del path, igual que find.
+        // Si és una carpeta, no el borrem.
+        match self.resolve(path)? {
+            ResolvedPath::File(entry) => self.delete_entry(&entry, path),
+            ResolvedPath::Dir(_) => Err(ShooterError::IsADirectory),
+        }
+    }
+
+    fn remove_dir(&mut self, path: &str) -> Result<DeleteReport, ShooterError> {
+        let entry = self.lookup(path)?.ok_or(ShooterError::RootDirectory)?;
+        if !entry.is_dir() {
+            return Err(ShooterError::NotADirectory);
+        }
+
+        // Un directori buit només conté . i ..
+        if self.read_dir(Some(entry.cluster))?.iter().any(|e| !e.is_hidden()) {
+            return Err(ShooterError::DirectoryNotEmpty);
+        }
+        self.delete_entry(&entry, path)
+    }
+
+    fn set_soft_delete(&mut self, soft: bool) -> Result<(), ShooterError> {
+        self.soft_delete = soft;
+        Ok(())
+    }
+
+    fn undelete(&mut self, path: &str) -> Result<FileEntry, ShooterError> {
+        let components = split_path(path);
+        let (name, parent) = components.split_last().ok_or(ShooterError::RootDirectory)?;
+        let dir_cluster = match self.resolve(&parent.join("/"))? {
+            ResolvedPath::Dir(dir_cluster) => dir_cluster,
+            ResolvedPath::File(_) => return Err(ShooterError::NotADirectory),
+        };
+
+        if self.read_dir(dir_cluster)?.iter().any(|e| e.matches(name)) {
+            return Err(ShooterError::AlreadyExists);
+        }
+
+        let (deleted, first_char) = self.read_deleted(dir_cluster)?
+            .into_iter()
+            .find_map(|d| d.first_char_for(name).map(|c| (d, c)))
+            .ok_or(ShooterError::FileNotFound)?;
+        if deleted.entry.is_dir() {
+            return Err(ShooterError::IsADirectory);
+        }
+
+        // No sabem com era la cadena: suposem que el fitxer ocupava clusters consecutius,
+        // i si algun ja no està lliure és que les dades s'han reutilitzat.
+        let cluster_size = self.bpb_sec_per_clus as u32 * self.bpb_byts_per_sec as u32;
+        let count = deleted.entry.size.div_ceil(cluster_size);
+        let first_cluster = deleted.entry.cluster;
+        // Un fitxer buit no té clusters: només cal tornar-li el nom. Un delete normal deixa una marca
+        // d'end of chain com a cluster inicial, i d'aquest fitxer ja no queda res per recuperar.
+        if count == 0 && self.is_end_of_chain(first_cluster) {
+            return Err(ShooterError::NotRecoverable);
+        }
+        if count > 0 && (first_cluster < 2 || first_cluster as u64 + count as u64 > self.cluster_count() as u64 + 2) {
+            return Err(ShooterError::NotRecoverable);
+        }
+        let chain: Vec<u32> = (first_cluster..first_cluster + count).collect();
+        for &cluster in &chain {
+            if self.next_cluster(cluster)? != 0 {
+                return Err(ShooterError::NotRecoverable);
+            }
+        }
+
+        for (k, &cluster) in chain.iter().enumerate() {
+            let next = chain.get(k + 1).copied().unwrap_or_else(|| self.end_of_chain_mark());
+            self.set_fat_entry(cluster, next)?;
+        }
+        if let Some(free) = self.fs_info_free_count() {
+            self.set_fs_info_free_count(free.saturating_sub(count))?;
+        }
 
-        let edited_file: Vec<u8> = self.delete_in_dir(first_root_dir_start, first_root_dir_end, &self.file_name);
+        // Restaurem el primer caràcter i, si són d'aquesta entrada, els ordinals de les LFN.
+        self.data[deleted.entry.offset as usize] = first_char;
+        if deleted.lfn_matches(first_char) {
+            let lfn_count = deleted.entry.lfn_offsets.len();
+            for (k, &lfn_offset) in deleted.entry.lfn_offsets.iter().enumerate() {
+                let ord = (lfn_count - k) as u8;
+                self.data[lfn_offset as usize] = if k == 0 { ord | 0x40 } else { ord };
+            }
+        }
+
+        let entry = self.lookup(path)?.ok_or(ShooterError::RootDirectory)?;
+        Ok(entry.to_file_entry(normalize_path(path)))
+    }
+
+    fn write_file(&mut self, path: &str, contents: &[u8]) -> Result<FileEntry, ShooterError> {
+        let size = u32::try_from(contents.len()).map_err(|_| ShooterError::NoSpace)?;
+        let cluster_size = self.bpb_sec_per_clus as u32 * self.bpb_byts_per_sec as u32;
+        let (clusters, _) = self.create_entry(path, 0x20, size, size.div_ceil(cluster_size))?;
+        for (chunk, &cluster) in contents.chunks(cluster_size as usize).zip(&clusters) {
+            let (start, end) = self.cluster_region(cluster)?;
+            let region = extract_slice_mut(&mut self.data, start as usize, (end - start) as usize)?;
+            region[..chunk.len()].copy_from_slice(chunk);
+            region[chunk.len()..].fill(0);
+        }
+
+        let entry = self.lookup(path)?.ok_or(ShooterError::RootDirectory)?;
+        Ok(entry.to_file_entry(normalize_path(path)))
+    }
+
+    fn create_dir(&mut self, path: &str) -> Result<FileEntry, ShooterError> {
+        let (clusters, parent_cluster) = self.create_entry(path, 0x10, 0, 1)?;
+        let (start, end) = self.cluster_region(clusters[0])?;
+        extract_slice_mut(&mut self.data, start as usize, (end - start) as usize)?.fill(0);
+
+        // . apunta al mateix directori i .. al pare.
+        let parent_cluster = self.dir_id(parent_cluster);
+        self.write_short_entry(start, b".          ", 0x10, clusters[0], 0)?;
+        self.write_short_entry(start + 32, b"..         ", 0x10, parent_cluster, 0)?;
+
+        let entry = self.lookup(path)?.ok_or(ShooterError::RootDirectory)?;
+        Ok(entry.to_file_entry(normalize_path(path)))
+    }
+
+    fn truncate(&mut self, path: &str, size: u32) -> Result<FileEntry, ShooterError> {
+        let entry = self.lookup(path)?.ok_or(ShooterError::RootDirectory)?;
+        if entry.is_dir() {
+            return Err(ShooterError::IsADirectory);
+        }
+        self.resize_entry(&entry, size)?;
+
+        let entry = self.lookup(path)?.ok_or(ShooterError::RootDirectory)?;
+        Ok(entry.to_file_entry(normalize_path(path)))
+    }
+
+    fn append(&mut self, path: &str, contents: &[u8]) -> Result<FileEntry, ShooterError> {
+        let entry = self.lookup(path)?.ok_or(ShooterError::RootDirectory)?;
+        if entry.is_dir() {
+            return Err(ShooterError::IsADirectory);
+        }
+        let size = u32::try_from(entry.size as u64 + contents.len() as u64).map_err(|_| ShooterError::NoSpace)?;
+        let chain = self.resize_entry(&entry, size)?;
+        self.write_at(&chain, entry.size, contents)?;
+
+        let entry = self.lookup(path)?.ok_or(ShooterError::RootDirectory)?;
+        Ok(entry.to_file_entry(normalize_path(path)))
+    }
 
-        if edited_file.len() != 0 {
-            fs::write(format!("{}{}", RESOURCES_PATH, self.vol_name), edited_file).expect("Unable to save new filesystem! Check program permissions!");
-            println!("{}{}{}", FILE_DELETED_1, self.file_name, FILE_DELETED_2);
+    fn rename(&mut self, src: &str, dst: &str) -> Result<FileEntry, ShooterError> {
+        let entry = self.lookup(src)?.ok_or(ShooterError::RootDirectory)?;
+        // . i .. no són entrades que es puguin moure.
+        if entry.name == "." || entry.name == ".." {
+            return Err(ShooterError::FileNotFound);
+        }
+        let (src_dir, _) = self.new_entry_parent(src)?;
+
+        // Si dst és un directori que ja existeix, hi movem src amb el mateix nom. Si dst és el mateix
+        // fitxer, només canvien majúscules i minúscules.
+        let (dst_dir, name, dst_path) = match self.lookup(dst) {
+            Ok(Some(found)) if found.offset == entry.offset => {
+                let (dir, name) = self.new_entry_parent(dst)?;
+                (dir, name.to_string(), normalize_path(dst))
+            }
+            Ok(None) => (None, entry.name.clone(), join_path("/", &entry.name)),
+            Ok(Some(found)) if found.is_dir() => (Some(found.cluster), entry.name.clone(), join_path(&normalize_path(dst), &entry.name)),
+            Ok(Some(_)) => return Err(ShooterError::AlreadyExists),
+            Err(ShooterError::FileNotFound) => {
+                let (dir, name) = self.new_entry_parent(dst)?;
+                (dir, name.to_string(), normalize_path(dst))
+            }
+            Err(e) => return Err(e),
+        };
+        if entry.is_dir() && self.is_ancestor(entry.cluster, dst_dir)? {
+            return Err(ShooterError::MoveIntoItself);
+        }
+
+        let same_dir = self.dir_id(src_dir) == self.dir_id(dst_dir);
+        let (short_name, long_name) = self.new_entry_name(dst_dir, &name, if same_dir { Some(entry.offset) } else { None })?;
+        let mut raw_entry = [0u8; 32];
+        raw_entry.copy_from_slice(extract_slice(&self.data, entry.offset as usize, 32)?);
+
+        // Dins del mateix directori, si el nom nou cap a les entrades actuals, el reescrivim al seu lloc
+        // i les que sobren queden esborrades. Si no, el posem a entrades noves i esborrem les velles.
+        let old_slots: Vec<u32> = entry.lfn_offsets.iter().copied().chain([entry.offset]).collect();
+        let needed = long_name.len() + 1;
+        let slots = if same_dir && needed <= old_slots.len() {
+            let (unused, slots) = old_slots.split_at(old_slots.len() - needed);
+            for &offset in unused {
+                self.data[offset as usize] = 0xE5;
+            }
+            slots.to_vec()
         } else {
-            println!("{}", FILE_NOT_FOUND);
+            let slots = self.reserve_dir_slots(dst_dir, needed, 0)?;
+            for &offset in &old_slots {
+                self.data[offset as usize] = 0xE5;
+            }
+            slots
+        };
+
+        // L'entrada 8.3 es copia sencera (atributs, dates, cluster i mida) amb el nom nou.
+        self.write_lfn_entries(&slots[..long_name.len()], &long_name, &short_name)?;
+        raw_entry[0..11].copy_from_slice(&short_name);
+        extract_slice_mut(&mut self.data, slots[long_name.len()] as usize, 32)?.copy_from_slice(&raw_entry);
+
+        // Un directori que canvia de pare: el seu .. (segona entrada del primer cluster) apunta al pare nou.
+        if entry.is_dir() && !same_dir {
+            let (start, _) = self.cluster_region(entry.cluster)?;
+            let dot_dot = (start + 32) as usize;
+            if extract_slice(&self.data, dot_dot, 11)? == b"..         " {
+                let parent_cluster = self.dir_id(dst_dir);
+                save_u16(&mut self.data, dot_dot + 26, parent_cluster as u16)?;
+                if self.fat_type == FatType::FAT32 {
+                    save_u16(&mut self.data, dot_dot + 20, (parent_cluster >> 16) as u16)?;
+                }
+            }
         }
+
+        let entry = self.lookup(&dst_path)?.ok_or(ShooterError::RootDirectory)?;
+        Ok(entry.to_file_entry(dst_path))
+    }
+
+    fn save(&self) -> Result<(), ShooterError> {
+        save_volume(&self.path, &self.data)
     }
 }
+
+#[cfg(test)]
+mod tests;
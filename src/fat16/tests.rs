@@ -0,0 +1,259 @@
+use super::*;
+
+// Crea en memòria un volum FAT del tipus demanat: sectors de 512 bytes, un sector per cluster i dues FATs.
+// El tipus surt només del nombre de clusters. En FAT32 el root directory és el cluster 2 i hi ha un FSInfo
+// al sector 1; en FAT12 i FAT16 el root directory té 64 entrades.
+fn mkfs(fat_type: FatType) -> FAT16 {
+    let (clusters, bits, reserved, root_entries): (u32, u32, u32, u32) = match fat_type {
+        FatType::FAT12 => (2000, 12, 1, 64),
+        FatType::FAT16 => (5000, 16, 1, 64),
+        FatType::FAT32 => (66000, 32, 32, 0),
+    };
+    let fat_size = ((clusters + 2) * bits / 8).div_ceil(512);
+    let total = reserved + 2 * fat_size + root_entries * 32 / 512 + clusters;
+    let mut data = vec![0; total as usize * 512];
+
+    data[3..11].copy_from_slice(b"MKFSTEST");
+    save_u16(&mut data, 11, 512).unwrap();
+    data[13] = 1;
+    save_u16(&mut data, 14, reserved as u16).unwrap();
+    data[16] = 2;
+    save_u16(&mut data, 17, root_entries as u16).unwrap();
+    if total < 0x10000 {
+        save_u16(&mut data, 19, total as u16).unwrap();
+    } else {
+        save_u32(&mut data, 32, total).unwrap();
+    }
+    data[21] = 0xF8;
+    save_u16(&mut data, 510, 0xAA55).unwrap();
+
+    if fat_type == FatType::FAT32 {
+        save_u32(&mut data, 36, fat_size).unwrap();
+        save_u32(&mut data, 44, 2).unwrap();
+        save_u16(&mut data, 48, 1).unwrap();
+        save_u16(&mut data, 50, 6).unwrap();
+        data[71..82].copy_from_slice(b"NO NAME    ");
+
+        // FSInfo: tots els clusters són lliures menys el del root directory.
+        save_u32(&mut data, 512, 0x4161_5252).unwrap();
+        save_u32(&mut data, 512 + 484, 0x6141_7272).unwrap();
+        save_u32(&mut data, 512 + 488, clusters - 1).unwrap();
+        save_u32(&mut data, 512 + 492, 0xFFFF_FFFF).unwrap();
+        save_u32(&mut data, 512 + 508, 0xAA55_0000).unwrap();
+    } else {
+        save_u16(&mut data, 22, fat_size as u16).unwrap();
+        data[43..54].copy_from_slice(b"NO NAME    ");
+    }
+
+    let mut fs = FAT16::new(GenericVolume { data, path: None }).unwrap();
+    assert_eq!(fs.fat_type, fat_type);
+
+    // Les entrades 0 i 1 de la FAT estan reservades.
+    fs.set_fat_entry(0, 0x0FFF_FFF8).unwrap();
+    let end_of_chain = fs.end_of_chain_mark();
+    fs.set_fat_entry(1, end_of_chain).unwrap();
+    if fat_type == FatType::FAT32 {
+        fs.set_fat_entry(2, end_of_chain).unwrap();
+    }
+    fs
+}
+
+// Clusters lliures segons la FAT.
+fn free_clusters(fs: &FAT16) -> u32 {
+    (2..fs.cluster_count() + 2).filter(|&c| fs.next_cluster(c).unwrap() == 0).count() as u32
+}
+
+// Entrada .. del directori path.
+fn dot_dot(fs: &FAT16, path: &str) -> u32 {
+    let dir = fs.lookup(path).unwrap().unwrap();
+    fs.read_dir(Some(dir.cluster)).unwrap().into_iter().find(|e| e.name == "..").unwrap().cluster
+}
+
+#[test]
+fn lfn_checksum_matches_spec() {
+    // Valors calculats amb l'algorisme de l'especificació (rotació a la dreta i suma).
+    assert_eq!(lfn_checksum(b"UNNOMB~1TXT"), 0x3B);
+    assert_eq!(lfn_checksum(b"README  TXT"), 0x73);
+}
+
+#[test]
+fn long_names_round_trip() {
+    for fat_type in [FatType::FAT12, FatType::FAT16, FatType::FAT32] {
+        let mut fs = mkfs(fat_type);
+        let name = "Un nom bastant llarg.txt";
+        fs.write_file(&format!("/{}", name), b"contingut").unwrap();
+
+        let entry = fs.lookup(name).unwrap().unwrap();
+        assert_eq!(entry.name, name);
+        assert_eq!(&entry.raw_name, b"UNNOMB~1TXT");
+        // 24 caràcters: dues entrades LFN, cadascuna amb el checksum del nom 8.3.
+        assert_eq!(entry.lfn_offsets.len(), 2);
+        for &offset in &entry.lfn_offsets {
+            assert_eq!(fs.data[offset as usize + 13], 0x3B);
+        }
+        assert_eq!(fs.data[entry.lfn_offsets[0] as usize], 0x42);
+        assert_eq!(fs.data[entry.lfn_offsets[1] as usize], 0x01);
+
+        let mut out = vec![];
+        fs.read_file(name, &mut out).unwrap();
+        assert_eq!(out, b"contingut");
+
+        // Si el checksum no coincideix, el nom llarg no és d'aquesta entrada i queda el nom 8.3.
+        fs.data[entry.lfn_offsets[1] as usize + 13] ^= 0xFF;
+        let entry = fs.lookup("unnomb~1.txt").unwrap().unwrap();
+        assert_eq!(entry.name, "unnomb~1.txt");
+        assert!(entry.lfn_offsets.is_empty());
+    }
+}
+
+#[test]
+fn fat12_entries_share_bytes() {
+    let mut fs = mkfs(FatType::FAT12);
+    fs.set_fat_entry(2, 0x123).unwrap();
+    fs.set_fat_entry(3, 0xABC).unwrap();
+    assert_eq!(fs.next_cluster(2).unwrap(), 0x123);
+    assert_eq!(fs.next_cluster(3).unwrap(), 0xABC);
+
+    // Els clusters 2 i 3 comparteixen el byte del mig, a les dues còpies de la FAT.
+    for fat_num in 0..2 {
+        let pos = fs.fat_entry_offset(fat_num, 2);
+        assert_eq!(fs.data[pos..pos + 3], [0x23, 0xC1, 0xAB]);
+    }
+
+    // Canviar una entrada no toca la veïna.
+    fs.set_fat_entry(2, 0).unwrap();
+    assert_eq!(fs.next_cluster(3).unwrap(), 0xABC);
+    fs.set_fat_entry(3, 0xFFF).unwrap();
+    assert_eq!(fs.next_cluster(2).unwrap(), 0);
+    assert_eq!(fs.next_cluster(3).unwrap(), 0xFFF);
+    assert_eq!(fs.next_cluster(4).unwrap(), 0);
+}
+
+#[test]
+fn fat12_chain_round_trip() {
+    let mut fs = mkfs(FatType::FAT12);
+    let free = free_clusters(&fs);
+
+    // Un primer fitxer d'un cluster fa que el segon comenci en un cluster senar.
+    fs.write_file("/A.BIN", &[1]).unwrap();
+    let contents: Vec<u8> = (0..7 * 512).map(|i| (i % 251) as u8).collect();
+    fs.write_file("/B.BIN", &contents).unwrap();
+
+    let entry = fs.lookup("B.BIN").unwrap().unwrap();
+    assert_eq!(entry.cluster % 2, 1);
+    assert_eq!(fs.cluster_chain(entry.cluster).unwrap().len(), 7);
+    let mut out = vec![];
+    fs.read_file("/B.BIN", &mut out).unwrap();
+    assert!(out == contents);
+
+    fs.delete("/A.BIN").unwrap();
+    fs.delete("/B.BIN").unwrap();
+    assert_eq!(free_clusters(&fs), free);
+}
+
+#[test]
+fn fat32_fs_info_free_count() {
+    let mut fs = mkfs(FatType::FAT32);
+    let check = |fs: &FAT16| assert_eq!(fs.fs_info_free_count(), Some(free_clusters(fs)));
+    check(&fs);
+
+    fs.write_file("/A.BIN", &[7; 3 * 512]).unwrap();
+    check(&fs);
+    fs.create_dir("/D").unwrap();
+    check(&fs);
+    fs.truncate("/A.BIN", 5 * 512).unwrap();
+    check(&fs);
+    fs.truncate("/A.BIN", 100).unwrap();
+    check(&fs);
+
+    fs.set_soft_delete(true).unwrap();
+    fs.delete("/A.BIN").unwrap();
+    check(&fs);
+    fs.undelete("/A.BIN").unwrap();
+    check(&fs);
+
+    fs.set_soft_delete(false).unwrap();
+    fs.delete("/A.BIN").unwrap();
+    fs.remove_dir("/D").unwrap();
+    check(&fs);
+    assert_eq!(fs.fs_info_free_count(), Some(fs.cluster_count() - 1));
+}
+
+#[test]
+fn directories_grow_by_clusters() {
+    for fat_type in [FatType::FAT12, FatType::FAT16, FatType::FAT32] {
+        let mut fs = mkfs(fat_type);
+        fs.create_dir("/D").unwrap();
+        let dir = fs.lookup("D").unwrap().unwrap().cluster;
+        assert_eq!(fs.cluster_chain(dir).unwrap().len(), 1);
+
+        // 16 entrades per cluster: . i .. i 40 fitxers amb nom llarg (dues entrades LFN i la 8.3 cadascun),
+        // 122 entrades en total.
+        let names: Vec<String> = (0..40).map(|i| format!("Fitxer número {}", i)).collect();
+        for name in &names {
+            fs.write_file(&format!("/D/{}", name), name.as_bytes()).unwrap();
+        }
+        assert_eq!(fs.cluster_chain(dir).unwrap().len(), 8);
+
+        let mut listed: Vec<String> = fs.list("/D").unwrap().into_iter().map(|e| e.name).collect();
+        let mut expected = names.clone();
+        listed.sort();
+        expected.sort();
+        assert_eq!(listed, expected);
+        for name in &names {
+            let mut out = vec![];
+            fs.read_file(&format!("/D/{}", name), &mut out).unwrap();
+            assert_eq!(out, name.as_bytes());
+        }
+    }
+}
+
+#[test]
+fn root_directory_growth() {
+    // En FAT32 el root directory és una cadena de clusters com qualsevol altre directori.
+    let mut fs = mkfs(FatType::FAT32);
+    for i in 0..40 {
+        fs.write_file(&format!("/F{}.TXT", i), b"x").unwrap();
+    }
+    assert_eq!(fs.cluster_chain(fs.root_cluster).unwrap().len(), 3);
+    assert_eq!(fs.list("/").unwrap().len(), 40);
+
+    // En FAT16 té una mida fixa: quan s'omple no hi cap res més.
+    let mut fs = mkfs(FatType::FAT16);
+    for i in 0..64 {
+        fs.write_file(&format!("/F{}.TXT", i), b"x").unwrap();
+    }
+    assert!(matches!(fs.write_file("/F64.TXT", b"x"), Err(ShooterError::NoSpace)));
+}
+
+#[test]
+fn rename_rewrites_dot_dot() {
+    for fat_type in [FatType::FAT12, FatType::FAT16, FatType::FAT32] {
+        let mut fs = mkfs(fat_type);
+        fs.create_dir("/A").unwrap();
+        fs.create_dir("/B").unwrap();
+        fs.create_dir("/A/SUB").unwrap();
+        let a = fs.lookup("A").unwrap().unwrap().cluster;
+        let b = fs.lookup("B").unwrap().unwrap().cluster;
+        fs.write_file("/A/SUB/F.TXT", b"f").unwrap();
+        assert_eq!(dot_dot(&fs, "/A/SUB"), a);
+
+        fs.rename("/A/SUB", "/B").unwrap();
+        assert_eq!(dot_dot(&fs, "/B/SUB"), b);
+
+        // Al root directory, .. val 0 fins i tot en FAT32.
+        fs.rename("/B/SUB", "/Moved").unwrap();
+        assert_eq!(dot_dot(&fs, "/Moved"), 0);
+
+        // Dins del mateix directori, .. no canvia.
+        fs.rename("/Moved", "/Renamed").unwrap();
+        assert_eq!(dot_dot(&fs, "/Renamed"), 0);
+        let mut out = vec![];
+        fs.read_file("/Renamed/F.TXT", &mut out).unwrap();
+        assert_eq!(out, b"f");
+
+        // No es pot moure un directori a dins d'un descendent seu.
+        fs.rename("/Renamed", "/A").unwrap();
+        assert!(matches!(fs.rename("/A", "/A/Renamed"), Err(ShooterError::MoveIntoItself)));
+    }
+}
@@ -1,94 +1,324 @@
+use std::collections::HashSet;
 use std::fs;
-use std::process::exit;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
 
-use crate::utils::extract_string;
+use crate::error::ShooterError;
+use crate::ext2::{Ext2, Ext2Info};
+use crate::fat16::{Fat16Info, FAT16};
+use crate::utils::{extract_string, normalize_path, split_path};
 
-pub(crate) const RESOURCES_PATH: &str = "./res/";
+// Carpeta on es busca el volum si el path donat no existeix.
+pub const RESOURCES_PATH: &str = "./res/";
 
-pub(crate) const INFO_HEADER: &str = "------ Filesystem Information ------";
-
-pub(crate) const FILE_FOUND: &str = "Fitxer trobat! Ocupa ";
-
-pub(crate) const FILE_DELETED_1: &str = "El fitxer ";
-
-pub(crate) const FILE_DELETED_2: &str = " ha estat eliminat.";
-
-
-
-// Program errors
-pub(crate) const FILE_NOT_FOUND: &str = "Error. Fitxer no trobat.";
-
-pub(crate) const ERROR_NUM_PARAMS_WRONG: &str = "Error. Nombre de paràmetres incorrecte";
-
-pub(crate) const ERROR_VOLUME_NOT_FOUND: &str = "Error. Volum no trobat.";
-
-pub(crate) const ERROR_OPTION_NOT_FOUND: &str = "Opcio no reconeguda! Opcions reconegudes són /info /find /delete";
-
-pub(crate) const ERROR_VOLUME_FORMAT_NOT_RECOGNIZED: &str = "Error. Volum no formatat en FAT16 ni EXT2.";
-
-pub(crate) const ERROR_FAT_12_FOUND: &str = "Filesystem must be FAT16. FAT12 found instead!";
-
-pub(crate) const ERROR_FAT_32_FOUND: &str = "Filesystem must be FAT16. FAT32 found instead!";
+// Nom de volum que indica llegir-lo de l'entrada estàndard.
+pub const STDIN_VOLUME: &str = "-";
 
 
 pub struct GenericVolume {
     pub(crate) data: Vec<u8>,
-    pub(crate) file_name: String,
-    pub(crate) vol_name: String,
-
+    /// Fitxer d'on s'ha llegit el volum. None si ve de l'entrada estàndard.
+    pub(crate) path: Option<PathBuf>,
 }
 
 impl GenericVolume {
-    pub(crate) fn new(volume_name: String, file_name: String) -> Self {
-        Self {
+    /// Llegeix un volum. `volume_name` pot ser un path absolut o relatiu, un nom dins de
+    /// [`RESOURCES_PATH`] o [`STDIN_VOLUME`] per llegir-lo de l'entrada estàndard (només lectura).
+    pub fn new(volume_name: &str) -> Result<Self, ShooterError> {
+        if volume_name == STDIN_VOLUME {
+            return Self::from_reader(io::stdin().lock());
+        }
+
+        let path = Self::resolve_path(volume_name);
+        Ok(Self {
             // Verifiquem que el fitxer existeix...
-            data: {
-                let r = fs::read(format!("{}{}", RESOURCES_PATH, volume_name));
-                if r.is_err() {
-                    println!("{}", ERROR_VOLUME_NOT_FOUND);
-                    exit(-1)
-                }
-                r.unwrap()
-            },
-            file_name,
-            vol_name: volume_name,
+            data: fs::read(&path).map_err(ShooterError::VolumeNotFound)?,
+            path: Some(path),
+        })
+    }
+
+    /// Llegeix un volum sencer d'un reader. El volum resultant no es pot guardar.
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Self, ShooterError> {
+        let mut data = vec![];
+        reader.read_to_end(&mut data).map_err(ShooterError::VolumeNotFound)?;
+        Ok(Self {
+            data,
+            path: None,
+        })
+    }
+
+    // Si el path no existeix tal qual, el busquem a la carpeta de recursos.
+    fn resolve_path(volume_name: &str) -> PathBuf {
+        let path = PathBuf::from(volume_name);
+        if path.exists() {
+            return path;
+        }
+
+        let fallback = Path::new(RESOURCES_PATH).join(volume_name);
+        if fallback.exists() {
+            fallback
+        } else {
+            path
         }
     }
 
-    pub(crate) fn is_fat(&self) -> bool {
-        if self.data.len() >= 511 {
+    pub fn is_fat(&self) -> bool {
+        if self.data.len() >= 512 {
             // BS_FilSysType contains FAT (One of the strings “FAT12 ”, “FAT16 ”, or “FAT ”.) in position 54.
+            // In FAT32 the extended BPB is larger and BS_FilSysType (“FAT32 ”) is in position 82.
             // Check sector 510 == 0x55 and sector 511 == 0xAA
-            extract_string(&self.data, 54, 8).unwrap().contains("FAT") && self.data[510..=511] == [0x55, 0xAA]
+            let fil_sys_type = |base| extract_string(&self.data, base, 8).is_ok_and(|s| s.contains("FAT"));
+            (fil_sys_type(54) || fil_sys_type(82)) && self.data[510..=511] == [0x55, 0xAA]
         } else {
             false
         }
     }
 
-    pub(crate) fn is_ext2(&self) -> bool {
-        if self.data.len() >= 1081 {
+    pub fn is_ext2(&self) -> bool {
+        if self.data.len() >= 1082 {
             // Mirem el magic number del filesystem
             self.data[1024 + 56..=1024 + 57] == [0x53, 0xEF]
         } else {
             false
         }
     }
+
+    // Crea el filesystem adequat segons el format detectat.
+    pub fn into_filesystem(self) -> Result<Box<dyn Filesystem>, ShooterError> {
+        if self.is_fat() {
+            Ok(Box::new(FAT16::new(self)?))
+        } else if self.is_ext2() {
+            Ok(Box::new(Ext2::new(self)?))
+        } else {
+            Err(ShooterError::UnrecognizedFormat)
+        }
+    }
+}
+
+// Escriu les dades modificades al fitxer del volum.
+pub(crate) fn save_volume(path: &Option<PathBuf>, data: &[u8]) -> Result<(), ShooterError> {
+    let path = path.as_ref().ok_or(ShooterError::ReadOnlyVolume)?;
+    fs::write(path, data).map_err(ShooterError::WriteFailed)
+}
+
+/// Informació general d'un volum, tal com la retorna [`Filesystem::info`].
+#[derive(Debug, Clone)]
+pub enum VolumeInfo {
+    Fat16(Fat16Info),
+    Ext2(Ext2Info),
+}
+
+/// Tipus d'una entrada de directori.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    File,
+    Directory,
+    /// Enllaç simbòlic. Només Ext2.
+    Symlink,
+    /// Dispositius, sockets, pipes...
+    Other,
+}
+
+/// Un fitxer trobat dins del volum.
+#[derive(Debug, Clone)]
+pub struct FileEntry {
+    pub name: String,
+    /// Path absolut dins del volum, p.ex. /dir/sub/file.txt
+    pub path: String,
+    pub file_type: FileType,
+    pub size: u32,
+    /// Número d'inode. Només el proporciona Ext2.
+    pub inode: Option<u32>,
+    /// Primer cluster de dades. Només el proporciona FAT.
+    pub cluster: Option<u32>,
+    /// Camp i_mode complet (tipus + permisos). Només el proporciona Ext2.
+    pub mode: Option<u16>,
+    /// Nombre d'entrades de directori que apunten a l'inode (i_links_count). Només el proporciona Ext2.
+    pub links: Option<u16>,
+    /// Timestamps UNIX. FAT no guarda la data de canvi i Ext2 no guarda la de creació.
+    pub created: Option<u32>,
+    pub modified: Option<u32>,
+    pub accessed: Option<u32>,
+    /// Path al qual apunta, si és un enllaç simbòlic.
+    pub link_target: Option<String>,
+}
+
+impl FileEntry {
+    pub fn is_dir(&self) -> bool {
+        self.file_type == FileType::Directory
+    }
+}
+
+/// Resum d'un fitxer eliminat.
+#[derive(Debug, Clone)]
+pub struct DeleteReport {
+    pub name: String,
+    /// Clusters (FAT) o blocs de dades (Ext2) alliberats.
+    pub freed_blocks: u32,
+    /// Blocs indirectes o nodes de l'arbre d'extents alliberats. Sempre 0 en FAT.
+    pub freed_index_blocks: u32,
+    /// Enllaços (hard links) que encara apunten a les dades. Si no és 0, no s'ha alliberat res.
+    pub remaining_links: u16,
+}
+
+/// Un inode esborrat, tal com el retorna [`Filesystem::list_deleted`].
+#[derive(Debug, Clone)]
+pub struct DeletedFile {
+    pub inode: u32,
+    pub file_type: FileType,
+    /// Camp i_mode complet (tipus + permisos).
+    pub mode: u16,
+    pub size: u32,
+    /// Timestamp UNIX de l'esborrat (i_dtime).
+    pub deleted: u32,
+    /// Blocs de dades i d'índex que tenia l'inode, i quants d'aquests encara estan lliures al bitmap.
+    pub blocks: u32,
+    pub free_blocks: u32,
 }
 
-pub(crate) trait Filesystem {
-    fn new(gv: GenericVolume) -> Self
+pub trait Filesystem {
+    fn new(gv: GenericVolume) -> Result<Self, ShooterError>
         where Self: Sized;
 
-    fn process_operation(&self, operation: String) {
-        match operation.as_str() {
-            "/info" => self.info(),
-            "/find" => self.find(),
-            "/delete" => self.delete(),
-            _ => println!("{}", ERROR_OPTION_NOT_FOUND),
+    fn info(&self) -> VolumeInfo;
+
+    /// Si `file_name` és un path (conté '/'), retorna el fitxer d'aquell path.
+    /// Si només és un nom, cerca recursivament tots els fitxers que es diuen així.
+    fn find(&self, file_name: &str) -> Result<Vec<FileEntry>, ShooterError>;
+
+    /// Llista el contingut del directori `path` (separat per '/', relatiu a l'arrel).
+    /// Si `path` és un fitxer, retorna només la seva entrada.
+    fn list(&self, path: &str) -> Result<Vec<FileEntry>, ShooterError>;
+
+    /// Escriu el contingut del fitxer `path` a `out`, exactament i_size / DIR_FileSize bytes.
+    /// Retorna el nombre de bytes escrits.
+    fn read_file(&self, path: &str, out: &mut dyn Write) -> Result<u64, ShooterError>;
+
+    /// Crea el fitxer `path` amb el contingut `contents`. El directori pare ha d'existir i no hi pot
+    /// haver cap entrada amb el mateix nom.
+    fn write_file(&mut self, _path: &str, _contents: &[u8]) -> Result<FileEntry, ShooterError> {
+        Err(ShooterError::Unsupported)
+    }
+
+    /// Crea el directori buit `path`. El directori pare ha d'existir i no hi pot haver cap entrada
+    /// amb el mateix nom.
+    fn create_dir(&mut self, _path: &str) -> Result<FileEntry, ShooterError> {
+        Err(ShooterError::Unsupported)
+    }
+
+    /// Canvia la mida del fitxer `path` a `size` bytes. Si creix, la part nova queda a zero.
+    fn truncate(&mut self, _path: &str, _size: u32) -> Result<FileEntry, ShooterError> {
+        Err(ShooterError::Unsupported)
+    }
+
+    /// Afegeix `contents` al final del fitxer `path`.
+    fn append(&mut self, _path: &str, _contents: &[u8]) -> Result<FileEntry, ShooterError> {
+        Err(ShooterError::Unsupported)
+    }
+
+    /// Canvia el nom de `src` o el mou a `dst`, sense copiar les dades. Si `dst` és un directori que ja
+    /// existeix, `src` hi va a parar amb el mateix nom.
+    fn rename(&mut self, _src: &str, _dst: &str) -> Result<FileEntry, ShooterError> {
+        Err(ShooterError::Unsupported)
+    }
+
+    /// Elimina el fitxer `path` de les dades en memòria. Un nom sense '/' es refereix al root directory.
+    /// Cal cridar [`Filesystem::save`] per persistir-ho.
+    fn delete(&mut self, path: &str) -> Result<DeleteReport, ShooterError>;
+
+    /// Elimina el directori buit `path`.
+    fn remove_dir(&mut self, path: &str) -> Result<DeleteReport, ShooterError>;
+
+    /// Elimina `path`. Si és un directori, n'elimina primer tot el contingut, recursivament.
+    /// Retorna la suma de tot el que s'ha alliberat.
+    fn delete_recursive(&mut self, path: &str) -> Result<DeleteReport, ShooterError> {
+        if split_path(path).is_empty() {
+            return Err(ShooterError::RootDirectory);
+        }
+        delete_tree(self, path, &mut HashSet::new())
+    }
+
+    /// Amb `soft`, els deletes següents alliberen l'espai però conserven les dades i els punters
+    /// (cluster inicial, mida...) perquè el fitxer es pugui recuperar amb [`Filesystem::undelete`].
+    fn set_soft_delete(&mut self, soft: bool) -> Result<(), ShooterError> {
+        if soft {
+            Err(ShooterError::Unsupported)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Recupera el fitxer esborrat `path`, si les seves dades encara no s'han reutilitzat.
+    fn undelete(&mut self, _path: &str) -> Result<FileEntry, ShooterError> {
+        Err(ShooterError::Unsupported)
+    }
+
+    /// Llista tots els inodes esborrats que encara tenen el mode, també aquells amb blocs que ja s'han
+    /// reutilitzat: [`DeletedFile::free_blocks`] diu quants en queden lliures, i només es poden recuperar
+    /// amb [`Filesystem::undelete_inode`] si ho són tots.
+    fn list_deleted(&self) -> Result<Vec<DeletedFile>, ShooterError> {
+        Err(ShooterError::Unsupported)
+    }
+
+    /// Escriu a `out` el contingut de l'inode esborrat `inode`, sense modificar el volum.
+    fn read_deleted(&self, _inode: u32, _out: &mut dyn Write) -> Result<u64, ShooterError> {
+        Err(ShooterError::Unsupported)
+    }
+
+    /// Recupera l'inode esborrat `inode` dins del volum, amb una nova entrada de directori a `path`.
+    fn undelete_inode(&mut self, _inode: u32, _path: &str) -> Result<FileEntry, ShooterError> {
+        Err(ShooterError::Unsupported)
+    }
+
+    /// Amb `follow`, els paths que passen per un enllaç simbòlic continuen pel path al qual apunta, també
+    /// si l'enllaç és l'últim component. Per defecte no fa res: el volum no té enllaços simbòlics.
+    fn set_follow_symlinks(&mut self, _follow: bool) -> Result<(), ShooterError> {
+        Ok(())
+    }
+
+    /// Retorna el path al qual apunta l'enllaç simbòlic `path`.
+    fn read_link(&self, _path: &str) -> Result<String, ShooterError> {
+        Err(ShooterError::Unsupported)
+    }
+
+    /// Copia les estructures principals del volum (superblock, descriptors de grup...) a les seves
+    /// còpies de seguretat, perquè reflecteixin els canvis fets. Per defecte no fa res.
+    fn update_backups(&mut self) -> Result<(), ShooterError> {
+        Ok(())
+    }
+
+    /// Escriu les dades del volum al fitxer d'on s'han llegit.
+    fn save(&self) -> Result<(), ShooterError>;
+}
+
+// Cos de Filesystem::delete_recursive. visited té l'inode (Ext2) o el primer cluster (FAT) dels subdirectoris
+// on ja hem entrat: si un hi torna a sortir, una entrada apunta a un ancestre i no acabaríem mai.
+fn delete_tree<F: Filesystem + ?Sized>(fs: &mut F, path: &str, visited: &mut HashSet<u32>) -> Result<DeleteReport, ShooterError> {
+    // Si no és un directori, és un delete normal.
+    match fs.delete(path) {
+        Err(ShooterError::IsADirectory) => {}
+        other => return other,
+    }
+
+    let mut report = DeleteReport {
+        name: normalize_path(path),
+        freed_blocks: 0,
+        freed_index_blocks: 0,
+        remaining_links: 0,
+    };
+    for entry in fs.list(path)? {
+        if let Some(id) = entry.inode.or(entry.cluster).filter(|_| entry.is_dir()) {
+            if !visited.insert(id) {
+                return Err(ShooterError::DirectoryCycle);
+            }
         }
+        let child = delete_tree(fs, &entry.path, visited)?;
+        report.freed_blocks += child.freed_blocks;
+        report.freed_index_blocks += child.freed_index_blocks;
     }
 
-    fn info(&self);
-    fn find(&self);
-    fn delete(&self);
+    let dir = fs.remove_dir(path)?;
+    report.freed_blocks += dir.freed_blocks;
+    report.freed_index_blocks += dir.freed_index_blocks;
+    Ok(report)
 }
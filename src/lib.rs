@@ -0,0 +1,15 @@
+//! The Shooter: eines per inspeccionar i modificar volums formatats en FAT16 i Ext2.
+//!
+//! Un volum es carrega amb [`GenericVolume`], que detecta el seu format i el converteix
+//! en la implementació de [`Filesystem`] corresponent.
+
+pub mod error;
+pub mod ext2;
+pub mod fat16;
+pub mod generics;
+mod utils;
+
+pub use error::ShooterError;
+pub use ext2::{Ext2, Ext2Info};
+pub use fat16::{Fat16Info, FatType, FAT16};
+pub use generics::{DeletedFile, DeleteReport, FileEntry, FileType, Filesystem, GenericVolume, VolumeInfo};
@@ -1,89 +1,618 @@
+use std::collections::HashSet;
 use std::env;
+use std::fs;
+use std::io::{self, Write};
 use std::process::exit;
 
-use ext2::Ext2;
-use fat16::FAT16;
-use generics::*;
+use chrono::DateTime;
 
-mod fat16;
-mod ext2;
-mod utils;
-mod generics;
+use the_shooter::*;
 
+const INFO_HEADER: &str = "------ Filesystem Information ------";
 
-fn main() {
-    let (operation, volume_name, file_name) = process_args();
+const FILE_FOUND_1: &str = "Fitxer trobat a ";
 
-    // Create a new generic FileSystem
-    let unknown_vol = GenericVolume::new(volume_name, file_name);
-    let filesystem: Box<dyn Filesystem>;
+const FILE_FOUND_2: &str = "! Ocupa ";
 
-    // Create an instance based on its type.
-    if unknown_vol.is_fat() {
-        filesystem = Box::new(FAT16::new(unknown_vol));
-    } else if unknown_vol.is_ext2() {
-        filesystem = Box::new(Ext2::new(unknown_vol));
-    } else {
-        println!("{}", ERROR_VOLUME_FORMAT_NOT_RECOGNIZED);
-        exit(-1);
-    }
+const LINK_FOUND_1: &str = "Enllaç simbòlic trobat a ";
 
-    // Satisfy the user needs.
-    filesystem.process_operation(operation);
+const LINK_FOUND_2: &str = "! Apunta a ";
+
+const FILE_DELETED_1: &str = "El fitxer ";
+
+const FILE_DELETED_2: &str = " ha estat eliminat.";
+
+const FILE_LINKS: &str = " Enllaços: ";
+
+const FILE_STILL_LINKED_1: &str = "L'inode encara té ";
+const FILE_STILL_LINKED_2: &str = " enllaços: les dades no s'han alliberat.";
+
+const BLOCKS_FREED_1: &str = "Blocs alliberats: ";
+const BLOCKS_FREED_2: &str = " de dades i ";
+const BLOCKS_FREED_3: &str = " d'índex.";
+
+const FILE_RECOVERED_1: &str = "El fitxer ";
+
+const FILE_RECOVERED_2: &str = " ha estat recuperat. Ocupa ";
+
+const INODE_RECOVERED_1: &str = "Inode ";
+
+const INODE_RECOVERED_2: &str = " recuperat a ";
+
+const LSDEL_HEADER: &str = "   Inode Mode             Mida Blocs lliures Esborrat";
+
+const FILE_COPIED_1: &str = "Fitxer copiat a ";
+
+const FILE_MOVED_1: &str = "Fitxer mogut a ";
+
+const FILE_RESIZED_1: &str = "Fitxer ";
+
+const FILE_RESIZED_2: &str = " redimensionat. Ocupa ";
+
+const DIR_CREATED_1: &str = "Directori ";
+
+const DIR_CREATED_2: &str = " creat.";
+
+const FILE_EXTRACTED_1: &str = "Fitxer extret a ";
+
+const FILE_EXTRACTED_2: &str = ". Ocupa ";
+
+const DIR_ALREADY_LISTED: &str = "Directori ja llistat: l'arbre de directoris té un cicle.";
+
+
+
+// Program errors
+const FILE_NOT_FOUND: &str = "Error. Fitxer no trobat.";
+
+const ERROR_NUM_PARAMS_WRONG: &str = "Error. Nombre de paràmetres incorrecte";
+
+const ERROR_FILE_NAME_NOT_SPECIFIED: &str = "File name not specified!";
+
+const ERROR_OPTION_NOT_FOUND: &str = "Opcio no reconeguda! Opcions reconegudes són /info /find /delete /rmdir /undelete /lsdel /ls /cat /extract /put /mkdir /mv /truncate /append /readlink";
+
+const ERROR_INVALID_SIZE: &str = "Error. La mida ha de ser un nombre de bytes.";
+
+const ERROR_INVALID_INODE: &str = "Error. L'inode ha de ser un nombre.";
+
+const ERROR_FLAG_NOT_FOUND: &str = "Error. Flag no reconegut per aquesta operació.";
+
+const ERROR_VOLUME_NOT_FOUND: &str = "Error. Volum no trobat.";
+
+const ERROR_VOLUME_FORMAT_NOT_RECOGNIZED: &str = "Error. Volum no formatat en FAT ni EXT2.";
+
+const ERROR_WRITE_FAILED: &str = "Unable to write! Check program permissions!";
+
+const ERROR_IS_A_DIRECTORY: &str = "Error. El path és un directori.";
+
+const ERROR_NOT_A_DIRECTORY: &str = "Error. El path no és un directori.";
+
+const ERROR_DIRECTORY_NOT_EMPTY: &str = "Error. El directori no és buit.";
+
+const ERROR_ROOT_DIRECTORY: &str = "Error. No es pot fer sobre el root directory.";
+
+const ERROR_READ_ONLY: &str = "Error. No es pot modificar un volum llegit de l'entrada estàndard.";
+
+const ERROR_UNSUPPORTED_FEATURES: &str = "Error. El volum fa servir funcionalitats que no es poden modificar:";
+
+const ERROR_NOT_RECOVERABLE: &str = "Error. Les dades del fitxer ja no es poden recuperar.";
+
+const ERROR_ALREADY_EXISTS: &str = "Error. Ja existeix un fitxer amb aquest nom.";
+
+const ERROR_UNSUPPORTED: &str = "Error. Operació no suportada per aquest tipus de volum.";
+
+const ERROR_NO_SPACE: &str = "Error. No queda espai lliure al volum.";
+
+const ERROR_NAME_TOO_LONG: &str = "Error. El nom és massa llarg.";
+
+const ERROR_READ_FAILED: &str = "Error. No es pot llegir el fitxer del host.";
+
+const ERROR_MOVE_INTO_ITSELF: &str = "Error. No es pot moure un directori a dins de si mateix.";
+
+const ERROR_SYMLINK_LOOP: &str = "Error. El path passa per massa enllaços simbòlics.";
+
+const ERROR_IS_A_SYMLINK: &str = "Error. El path és un enllaç simbòlic.";
+
+const ERROR_NOT_A_SYMLINK: &str = "Error. El path no és un enllaç simbòlic.";
+
+const ERROR_DIRECTORY_CYCLE: &str = "Error. L'arbre de directoris o una cadena de clusters del volum té un cicle.";
+
+// Exit codes. Cada variant de ShooterError té el seu.
+const EXIT_USAGE: i32 = 1;
+const EXIT_VOLUME_NOT_FOUND: i32 = 2;
+const EXIT_UNRECOGNIZED_FORMAT: i32 = 3;
+const EXIT_UNSUPPORTED_FAT: i32 = 4;
+const EXIT_INVALID_NAME: i32 = 5;
+const EXIT_TRUNCATED_IMAGE: i32 = 6;
+const EXIT_OUT_OF_BOUNDS: i32 = 7;
+const EXIT_FILE_NOT_FOUND: i32 = 8;
+const EXIT_WRITE_FAILED: i32 = 9;
+const EXIT_READ_ONLY: i32 = 10;
+const EXIT_IS_A_DIRECTORY: i32 = 11;
+const EXIT_UNSUPPORTED_FEATURES: i32 = 12;
+const EXIT_NOT_A_DIRECTORY: i32 = 13;
+const EXIT_DIRECTORY_NOT_EMPTY: i32 = 14;
+const EXIT_ROOT_DIRECTORY: i32 = 15;
+const EXIT_NOT_RECOVERABLE: i32 = 16;
+const EXIT_ALREADY_EXISTS: i32 = 17;
+const EXIT_UNSUPPORTED: i32 = 18;
+const EXIT_NO_SPACE: i32 = 19;
+const EXIT_NAME_TOO_LONG: i32 = 20;
+const EXIT_READ_FAILED: i32 = 21;
+const EXIT_MOVE_INTO_ITSELF: i32 = 22;
+const EXIT_SYMLINK_LOOP: i32 = 23;
+const EXIT_IS_A_SYMLINK: i32 = 24;
+const EXIT_NOT_A_SYMLINK: i32 = 25;
+const EXIT_DIRECTORY_CYCLE: i32 = 26;
+
+
+// Operacions reconegudes: nom, mínim i màxim de paràmetres després del volum, i flags admesos.
+const OPERATIONS: &[(&str, usize, usize, &[&str])] = &[
+    ("/info", 0, 0, &[]),
+    ("/find", 1, 1, &[]),
+    ("/delete", 1, 1, &["-b", "-r", "-s"]),
+    ("/rmdir", 1, 1, &["-b", "-s"]),
+    ("/undelete", 1, 2, &["-i"]),
+    ("/lsdel", 0, 0, &[]),
+    ("/ls", 0, 1, &["-l", "-R", "-L"]),
+    ("/cat", 1, 1, &["-L"]),
+    ("/extract", 2, 2, &["-L"]),
+    ("/put", 2, 2, &[]),
+    ("/mkdir", 1, 1, &[]),
+    ("/mv", 2, 2, &[]),
+    ("/truncate", 2, 2, &[]),
+    ("/append", 2, 2, &[]),
+    ("/readlink", 1, 1, &[]),
+];
+
+// Arguments del programa ja validats.
+struct Args {
+    operation: String,
+    volume_name: String,
+    params: Vec<String>,
+    flags: Vec<String>,
 }
 
+impl Args {
+    fn has_flag(&self, flag: &str) -> bool {
+        self.flags.iter().any(|f| f == flag)
+    }
+}
 
-// Extract the program arguments
-fn process_args() -> (String, String, String) {
-    let operation = {
-        let arg = env::args().nth(1);
-        if arg.is_none() {
-            exit_with_params_error();
+
+fn main() {
+    let args = match process_args() {
+        Ok(args) => args,
+        Err(message) => {
+            println!("{}", message);
+            exit(EXIT_USAGE);
         }
-        arg.unwrap()
     };
 
-    //Check que el nombre de args és el correcte basat en l'operation.
-    if operation == "/info" {
-        if env::args().count() != 3 {
-            exit_with_params_error();
-        }
-    } else {
-        if env::args().count() != 4 {
-            exit_with_params_error();
-        }
+    if let Err(e) = run(&args) {
+        println!("{}", error_message(&e));
+        exit(exit_code(&e));
     }
+}
 
-    let volume_name = {
-        let arg = env::args().nth(2);
-        if arg.is_none() {
-            exit_with_params_error();
-        }
-        arg.unwrap()
-    };
+fn run(args: &Args) -> Result<(), ShooterError> {
+    // Create a new generic FileSystem and an instance based on its type.
+    let mut filesystem = GenericVolume::new(&args.volume_name)?.into_filesystem()?;
+    filesystem.set_follow_symlinks(args.has_flag("-L"))?;
 
-    let file_name = {
-        let arg = env::args().nth(3);
-        if arg.is_some() {
-            if operation == "/info" {
-                exit_with_params_error();
+    // Satisfy the user needs.
+    match args.operation.as_str() {
+        "/info" => print_info(&filesystem.info()),
+        "/find" => {
+            let found = filesystem.find(&args.params[0])?;
+            if found.is_empty() {
+                return Err(ShooterError::FileNotFound);
+            }
+            for entry in found {
+                match (&entry.link_target, entry.links) {
+                    (Some(target), _) => println!("{}{}{}{}.", LINK_FOUND_1, entry.path, LINK_FOUND_2, target),
+                    (None, Some(links)) => println!("{}{}{}{} bytes.{}{}.", FILE_FOUND_1, entry.path, FILE_FOUND_2, entry.size, FILE_LINKS, links),
+                    (None, None) => println!("{}{}{}{} bytes.", FILE_FOUND_1, entry.path, FILE_FOUND_2, entry.size),
+                }
+            }
+        }
+        "/delete" | "/rmdir" => {
+            filesystem.set_soft_delete(args.has_flag("-s"))?;
+            let report = if args.operation == "/rmdir" {
+                filesystem.remove_dir(&args.params[0])?
+            } else if args.has_flag("-r") {
+                filesystem.delete_recursive(&args.params[0])?
             } else {
-                arg.unwrap()
+                filesystem.delete(&args.params[0])?
+            };
+            if args.has_flag("-b") {
+                filesystem.update_backups()?;
             }
-        } else {
-            if operation != "/info" {
-                println!("File name not specified!");
-                exit(-1);
+            filesystem.save()?;
+            println!("{}{}{}", FILE_DELETED_1, report.name, FILE_DELETED_2);
+            if report.remaining_links > 0 {
+                println!("{}{}{}", FILE_STILL_LINKED_1, report.remaining_links, FILE_STILL_LINKED_2);
+            } else {
+                println!("{}{}{}{}{}", BLOCKS_FREED_1, report.freed_blocks, BLOCKS_FREED_2, report.freed_index_blocks, BLOCKS_FREED_3);
+            }
+        }
+        "/undelete" if args.params.len() == 1 => {
+            let entry = filesystem.undelete(&args.params[0])?;
+            filesystem.save()?;
+            println!("{}{}{}{} bytes.", FILE_RECOVERED_1, entry.path, FILE_RECOVERED_2, entry.size);
+        }
+        "/undelete" => {
+            // Amb dos paràmetres, el primer és un número d'inode de /lsdel. Ja s'ha comprovat a
+            // process_args que és un nombre.
+            let inode = args.params[0].parse().unwrap_or_else(|_| unreachable!());
+            if args.has_flag("-i") {
+                let entry = filesystem.undelete_inode(inode, &args.params[1])?;
+                filesystem.save()?;
+                println!("{}{}{}{} bytes.", FILE_RECOVERED_1, entry.path, FILE_RECOVERED_2, entry.size);
             } else {
-                return (operation, volume_name, String::new());
+                let mut contents = vec![];
+                let size = filesystem.read_deleted(inode, &mut contents)?;
+                fs::write(&args.params[1], contents).map_err(ShooterError::WriteFailed)?;
+                println!("{}{}{}{}{}{} bytes.", INODE_RECOVERED_1, inode, INODE_RECOVERED_2, args.params[1], FILE_EXTRACTED_2, size);
             }
         }
+        "/lsdel" => {
+            println!("{}", LSDEL_HEADER);
+            for deleted in filesystem.list_deleted()? {
+                println!("{}", format_deleted_file(&deleted));
+            }
+        }
+        "/ls" => {
+            let path = args.params.first().map(String::as_str).unwrap_or("/");
+            print_list(filesystem.as_ref(), path, args.has_flag("-l"), args.has_flag("-R"), &mut HashSet::new())?;
+        }
+        "/cat" => {
+            let stdout = io::stdout();
+            let mut out = stdout.lock();
+            filesystem.read_file(&args.params[0], &mut out)?;
+            out.flush().map_err(ShooterError::WriteFailed)?;
+        }
+        "/extract" => {
+            // Llegim primer a memòria per no deixar un fitxer a mitges al host si el volum està malament.
+            let mut contents = vec![];
+            let size = filesystem.read_file(&args.params[0], &mut contents)?;
+            fs::write(&args.params[1], contents).map_err(ShooterError::WriteFailed)?;
+            println!("{}{}{}{} bytes.", FILE_EXTRACTED_1, args.params[1], FILE_EXTRACTED_2, size);
+        }
+        "/put" => {
+            let contents = fs::read(&args.params[0]).map_err(ShooterError::ReadFailed)?;
+            let entry = filesystem.write_file(&args.params[1], &contents)?;
+            filesystem.save()?;
+            println!("{}{}{}{} bytes.", FILE_COPIED_1, entry.path, FILE_EXTRACTED_2, entry.size);
+        }
+        "/mkdir" => {
+            let entry = filesystem.create_dir(&args.params[0])?;
+            filesystem.save()?;
+            println!("{}{}{}", DIR_CREATED_1, entry.path, DIR_CREATED_2);
+        }
+        "/mv" => {
+            let entry = filesystem.rename(&args.params[0], &args.params[1])?;
+            filesystem.save()?;
+            println!("{}{}.", FILE_MOVED_1, entry.path);
+        }
+        "/truncate" => {
+            // Ja s'ha comprovat a process_args que és un nombre.
+            let size = args.params[1].parse().unwrap_or_else(|_| unreachable!());
+            let entry = filesystem.truncate(&args.params[0], size)?;
+            filesystem.save()?;
+            println!("{}{}{}{} bytes.", FILE_RESIZED_1, entry.path, FILE_RESIZED_2, entry.size);
+        }
+        "/append" => {
+            let contents = fs::read(&args.params[1]).map_err(ShooterError::ReadFailed)?;
+            let entry = filesystem.append(&args.params[0], &contents)?;
+            filesystem.save()?;
+            println!("{}{}{}{} bytes.", FILE_RESIZED_1, entry.path, FILE_RESIZED_2, entry.size);
+        }
+        "/readlink" => println!("{}", filesystem.read_link(&args.params[0])?),
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+// Mostra el contingut d'un directori. Amb recursive, també el de tots els seus subdirectoris. visited té
+// l'inode (Ext2) o el primer cluster (FAT) dels subdirectoris ja llistats.
+fn print_list(filesystem: &dyn Filesystem, path: &str, long: bool, recursive: bool, visited: &mut HashSet<u32>) -> Result<(), ShooterError> {
+    let entries = filesystem.list(path)?;
+
+    if recursive {
+        println!("{}:", path);
+    }
+    for entry in &entries {
+        if long {
+            println!("{}", format_long_entry(entry));
+        } else if entry.is_dir() {
+            println!("{}/", entry.name);
+        } else {
+            println!("{}", entry.name);
+        }
+    }
+
+    if recursive {
+        for entry in entries.iter().filter(|e| e.is_dir()) {
+            println!();
+            let subdir = format!("{}/{}", path.trim_end_matches('/'), entry.name);
+            // Si ja l'hem llistat, una entrada apunta a un ancestre: no hi tornem a entrar.
+            if entry.inode.or(entry.cluster).is_some_and(|id| !visited.insert(id)) {
+                println!("{}:", subdir);
+                println!("{}", DIR_ALREADY_LISTED);
+                continue;
+            }
+            print_list(filesystem, &subdir, long, recursive, visited)?;
+        }
+    }
+    Ok(())
+}
+
+// Format llarg, a l'estil de ls -l: [inode] tipus/permisos, mida, modificació, últim accés i nom (amb el destí si és un enllaç simbòlic).
+fn format_long_entry(entry: &FileEntry) -> String {
+    let type_char = type_char(entry.file_type);
+
+    let mode = match entry.mode {
+        Some(mode) => format!("{}{}", type_char, permissions_to_string(mode)),
+        None => format!("{:<10}", type_char),
+    };
+
+    let inode = match entry.inode {
+        Some(inode) => format!("{:>8} ", inode),
+        None => String::new(),
+    };
+
+    let links = match entry.links {
+        Some(links) => format!(" {:>3}", links),
+        None => String::new(),
     };
 
-    (operation, volume_name, file_name)
+    let link_target = match &entry.link_target {
+        Some(target) => format!(" -> {}", target),
+        None => String::new(),
+    };
+
+    format!("{}{}{} {:>10} {} {} {}{}",
+            inode,
+            mode,
+            links,
+            entry.size,
+            format_optional_date(entry.modified),
+            format_optional_date(entry.accessed),
+            entry.name,
+            link_target)
+}
+
+// Una línia de /lsdel: inode, tipus/permisos, mida, blocs encara lliures i data d'esborrat.
+fn format_deleted_file(deleted: &DeletedFile) -> String {
+    format!("{:>8} {}{} {:>10} {:>6}/{:<6} {}",
+            deleted.inode,
+            type_char(deleted.file_type),
+            permissions_to_string(deleted.mode),
+            deleted.size,
+            deleted.free_blocks,
+            deleted.blocks,
+            format_optional_date(Some(deleted.deleted)))
+}
+
+fn type_char(file_type: FileType) -> char {
+    match file_type {
+        FileType::Directory => 'd',
+        FileType::File => '-',
+        FileType::Symlink => 'l',
+        FileType::Other => '?',
+    }
+}
+
+// Converteix els 9 bits de permisos a rwxrwxrwx.
+fn permissions_to_string(mode: u16) -> String {
+    let letters = ['r', 'w', 'x'];
+    (0..9).map(|bit| if mode & (1 << (8 - bit)) != 0 { letters[bit % 3] } else { '-' }).collect()
 }
 
-fn exit_with_params_error() -> ! {
-    println!("{}", ERROR_NUM_PARAMS_WRONG);
-    exit(-1);
-}
\ No newline at end of file
+fn format_optional_date(timestamp: Option<u32>) -> String {
+    match timestamp.and_then(|t| DateTime::from_timestamp(t as i64, 0)) {
+        Some(time) => time.format("%Y-%m-%d %H:%M").to_string(),
+        None => format!("{:<16}", "-"),
+    }
+}
+
+fn error_message(e: &ShooterError) -> String {
+    match e {
+        ShooterError::VolumeNotFound(_) => ERROR_VOLUME_NOT_FOUND.to_string(),
+        ShooterError::UnrecognizedFormat => ERROR_VOLUME_FORMAT_NOT_RECOGNIZED.to_string(),
+        ShooterError::FileNotFound => FILE_NOT_FOUND.to_string(),
+        ShooterError::IsADirectory => ERROR_IS_A_DIRECTORY.to_string(),
+        ShooterError::NotADirectory => ERROR_NOT_A_DIRECTORY.to_string(),
+        ShooterError::DirectoryNotEmpty => ERROR_DIRECTORY_NOT_EMPTY.to_string(),
+        ShooterError::RootDirectory => ERROR_ROOT_DIRECTORY.to_string(),
+        ShooterError::WriteFailed(e) => format!("{} ({})", ERROR_WRITE_FAILED, e),
+        ShooterError::ReadOnlyVolume => ERROR_READ_ONLY.to_string(),
+        ShooterError::UnsupportedFeatures(features) => format!("{} {}", ERROR_UNSUPPORTED_FEATURES, features.join(" ")),
+        ShooterError::NotRecoverable => ERROR_NOT_RECOVERABLE.to_string(),
+        ShooterError::AlreadyExists => ERROR_ALREADY_EXISTS.to_string(),
+        ShooterError::Unsupported => ERROR_UNSUPPORTED.to_string(),
+        ShooterError::NoSpace => ERROR_NO_SPACE.to_string(),
+        ShooterError::NameTooLong => ERROR_NAME_TOO_LONG.to_string(),
+        ShooterError::ReadFailed(e) => format!("{} ({})", ERROR_READ_FAILED, e),
+        ShooterError::MoveIntoItself => ERROR_MOVE_INTO_ITSELF.to_string(),
+        ShooterError::SymlinkLoop => ERROR_SYMLINK_LOOP.to_string(),
+        ShooterError::IsASymlink => ERROR_IS_A_SYMLINK.to_string(),
+        ShooterError::NotASymlink => ERROR_NOT_A_SYMLINK.to_string(),
+        ShooterError::DirectoryCycle => ERROR_DIRECTORY_CYCLE.to_string(),
+        other => format!("Error. {}", other),
+    }
+}
+
+fn exit_code(e: &ShooterError) -> i32 {
+    match e {
+        ShooterError::VolumeNotFound(_) => EXIT_VOLUME_NOT_FOUND,
+        ShooterError::UnrecognizedFormat => EXIT_UNRECOGNIZED_FORMAT,
+        ShooterError::UnsupportedFat(_) => EXIT_UNSUPPORTED_FAT,
+        ShooterError::InvalidName(_) => EXIT_INVALID_NAME,
+        ShooterError::TruncatedImage { .. } => EXIT_TRUNCATED_IMAGE,
+        ShooterError::OutOfBounds { .. } => EXIT_OUT_OF_BOUNDS,
+        ShooterError::FileNotFound => EXIT_FILE_NOT_FOUND,
+        ShooterError::WriteFailed(_) => EXIT_WRITE_FAILED,
+        ShooterError::ReadOnlyVolume => EXIT_READ_ONLY,
+        ShooterError::IsADirectory => EXIT_IS_A_DIRECTORY,
+        ShooterError::NotADirectory => EXIT_NOT_A_DIRECTORY,
+        ShooterError::DirectoryNotEmpty => EXIT_DIRECTORY_NOT_EMPTY,
+        ShooterError::RootDirectory => EXIT_ROOT_DIRECTORY,
+        ShooterError::UnsupportedFeatures(_) => EXIT_UNSUPPORTED_FEATURES,
+        ShooterError::NotRecoverable => EXIT_NOT_RECOVERABLE,
+        ShooterError::AlreadyExists => EXIT_ALREADY_EXISTS,
+        ShooterError::Unsupported => EXIT_UNSUPPORTED,
+        ShooterError::NoSpace => EXIT_NO_SPACE,
+        ShooterError::NameTooLong => EXIT_NAME_TOO_LONG,
+        ShooterError::ReadFailed(_) => EXIT_READ_FAILED,
+        ShooterError::MoveIntoItself => EXIT_MOVE_INTO_ITSELF,
+        ShooterError::SymlinkLoop => EXIT_SYMLINK_LOOP,
+        ShooterError::IsASymlink => EXIT_IS_A_SYMLINK,
+        ShooterError::NotASymlink => EXIT_NOT_A_SYMLINK,
+        ShooterError::DirectoryCycle => EXIT_DIRECTORY_CYCLE,
+    }
+}
+
+fn print_info(info: &VolumeInfo) {
+    match info {
+        VolumeInfo::Fat16(fat) => println!("{}\n
+Filesystem: {}
+System Name: {}
+Mida del sector: {}
+Sectors Per Cluster: {}
+Sectors reservats: {}
+Número de FATs: {}
+MaxRootEntries: {}
+Sectors per FAT: {}
+Clusters: {}
+Label: {}{}",
+                                          INFO_HEADER,
+                                          fat.fat_type,
+                                          fat.oem_name,
+                                          fat.bytes_per_sector,
+                                          fat.sectors_per_cluster,
+                                          fat.reserved_sectors,
+                                          fat.num_fats,
+                                          fat.max_root_entries,
+                                          fat.sectors_per_fat,
+                                          fat.cluster_count,
+                                          fat.label,
+                                          format_fat32_info(fat)),
+        VolumeInfo::Ext2(ext2) => println!("{}\n
+Filesystem: EXT2\n
+INFO INODE
+Mida Inode: {}
+Num Inodes: {}
+Primer Inode: {}
+Inodes Grup: {}
+Inodes Lliures: {}\n
+INFO BLOC
+Mida Bloc: {}
+Blocs Reservats: {}
+Blocs Lliures: {}
+Total Blocs: {}
+Primer Bloc: {}
+Blocs grup: {}
+Frags grup: {}
+Grups de blocs: {}\n
+INFO VOLUM
+Nom volum: {}
+Funcionalitats: {}
+Ultima comprov: {}
+Ultim muntatge: {}
+Ultima escriptura: {}", INFO_HEADER,
+                                          ext2.inode_size,
+                                          ext2.inode_count,
+                                          ext2.first_inode,
+                                          ext2.inodes_per_group,
+                                          ext2.free_inodes,
+                                          ext2.block_size,
+                                          ext2.reserved_blocks,
+                                          ext2.free_blocks,
+                                          ext2.block_count,
+                                          ext2.first_block,
+                                          ext2.blocks_per_group,
+                                          ext2.frags_per_group,
+                                          ext2.group_count,
+                                          ext2.volume_name,
+                                          ext2.features.join(" "),
+                                          timestamp_to_date_time(ext2.last_check),
+                                          timestamp_to_date_time(ext2.last_mount),
+                                          timestamp_to_date_time(ext2.last_write),
+        ),
+    }
+}
+
+// Camps del BPB estès de FAT32. Buit per FAT12 i FAT16.
+fn format_fat32_info(fat: &Fat16Info) -> String {
+    let mut text = String::new();
+    if let Some(root_cluster) = fat.root_cluster {
+        text += &format!("\nCluster del root: {}", root_cluster);
+    }
+    if let Some(fs_info_sector) = fat.fs_info_sector {
+        text += &format!("\nSector FSInfo: {}", fs_info_sector);
+    }
+    if let Some(backup_boot_sector) = fat.backup_boot_sector {
+        text += &format!("\nSector backup boot: {}", backup_boot_sector);
+    }
+    if let Some(free_clusters) = fat.free_clusters {
+        text += &format!("\nClusters lliures: {}", free_clusters);
+    }
+    text
+}
+
+fn timestamp_to_date_time(timestamp: u32) -> String {
+    let time = DateTime::from_timestamp(timestamp as i64, 0).unwrap_or_default();
+    time.format("%a %b %e %T %Y").to_string()
+}
+
+
+// Extract the program arguments
+fn process_args() -> Result<Args, &'static str> {
+    let mut args = env::args().skip(1);
+    let operation = args.next().ok_or(ERROR_NUM_PARAMS_WRONG)?;
+
+    let (_, min_params, max_params, allowed_flags) = *OPERATIONS.iter()
+        .find(|(name, ..)| *name == operation)
+        .ok_or(ERROR_OPTION_NOT_FOUND)?;
+
+    let volume_name = args.next().ok_or(ERROR_NUM_PARAMS_WRONG)?;
+
+    // Separem els flags (-l, -R o -lR) dels paràmetres. "-" sol és un paràmetre (stdin).
+    let mut params = vec![];
+    let mut flags = vec![];
+    for arg in args {
+        if arg.len() > 1 && arg.starts_with('-') {
+            for c in arg.chars().skip(1) {
+                let flag = format!("-{}", c);
+                if !allowed_flags.contains(&flag.as_str()) {
+                    return Err(ERROR_FLAG_NOT_FOUND);
+                }
+                flags.push(flag);
+            }
+        } else {
+            params.push(arg);
+        }
+    }
+
+    //Check que el nombre de args és el correcte basat en l'operation.
+    if params.len() < min_params {
+        return Err(if min_params == 1 { ERROR_FILE_NAME_NOT_SPECIFIED } else { ERROR_NUM_PARAMS_WRONG });
+    }
+    if params.len() > max_params {
+        return Err(ERROR_NUM_PARAMS_WRONG);
+    }
+    if operation == "/truncate" && params[1].parse::<u32>().is_err() {
+        return Err(ERROR_INVALID_SIZE);
+    }
+    if operation == "/undelete" && params.len() == 2 && params[0].parse::<u32>().is_err() {
+        return Err(ERROR_INVALID_INODE);
+    }
+
+    Ok(Args {
+        operation,
+        volume_name,
+        params,
+        flags,
+    })
+}
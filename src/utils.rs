@@ -1,73 +1,132 @@
-extern crate chrono;
-
 use std::str;
-use std::str::Utf8Error;
 
-use chrono::prelude::*;
 use std::time::{UNIX_EPOCH, SystemTime};
 
-pub(crate) fn is_power(x: usize, y:u64) -> bool{
-    let mut x = x as f64;
-    let y = y as f64;
+use chrono::{DateTime, Datelike, NaiveDate, Timelike};
 
-    while x % y == 0.0 {
-        x = x / y
-    }
-    return x == 1.0
+use crate::error::ShooterError;
+
+// Retorna el tros de data [base, base + len). Si surt del volum, retorna OutOfBounds.
+pub(crate) fn extract_slice(data: &[u8], base: usize, len: usize) -> Result<&[u8], ShooterError> {
+    base.checked_add(len)
+        .and_then(|end| data.get(base..end))
+        .ok_or(ShooterError::OutOfBounds { offset: base, len })
+}
+
+pub(crate) fn extract_slice_mut(data: &mut [u8], base: usize, len: usize) -> Result<&mut [u8], ShooterError> {
+    base.checked_add(len)
+        .and_then(move |end| data.get_mut(base..end))
+        .ok_or(ShooterError::OutOfBounds { offset: base, len })
 }
 
-pub(crate) fn extract_string(data: &[u8], base: usize, offset: usize) -> Result<&str, Utf8Error> {
-    str::from_utf8(&data[base..base + offset])
+pub(crate) fn extract_string(data: &[u8], base: usize, offset: usize) -> Result<&str, ShooterError> {
+    Ok(str::from_utf8(extract_slice(data, base, offset)?)?)
 }
 
 // Extrau un string fins a trobar un \0
-pub(crate) fn extract_string_terminated(data: &[u8], base: usize, offset: usize) -> Result<&str, Utf8Error> {
-    let vec = &data[base..base + offset];
+pub(crate) fn extract_string_terminated(data: &[u8], base: usize, offset: usize) -> Result<&str, ShooterError> {
+    let vec = extract_slice(data, base, offset)?;
 
     // Si la cadena son tot 0, retornem un valor indicatiu de que no hi ha res.
     if vec.iter().all(|&x| x == 0) {
         Ok("<Not defined>")
     } else {
-        Ok(str::from_utf8(vec).unwrap().split("\0").collect::<Vec<_>>()[0])
+        let end = vec.iter().position(|&x| x == 0).unwrap_or(vec.len());
+        Ok(str::from_utf8(&vec[..end])?)
     }
 }
 
-pub(crate) fn extract_u16(data: &[u8], base: usize) -> u16 {
-    let vec = &data[base..base + 2];
-    ((vec[1] as u16) << 8) | vec[0] as u16
+pub(crate) fn extract_u16(data: &[u8], base: usize) -> Result<u16, ShooterError> {
+    let vec = extract_slice(data, base, 2)?;
+    Ok(((vec[1] as u16) << 8) | vec[0] as u16)
 }
 
-pub(crate) fn extract_u32(data: &[u8], base: usize) -> u32 {
-    let vec = &data[base..base + 4];
-    ((vec[3] as u32) << 24) | ((vec[2] as u32) << 16) | ((vec[1] as u32) << 8) | (vec[0] as u32)
+pub(crate) fn extract_u32(data: &[u8], base: usize) -> Result<u32, ShooterError> {
+    let vec = extract_slice(data, base, 4)?;
+    Ok(((vec[3] as u32) << 24) | ((vec[2] as u32) << 16) | ((vec[1] as u32) << 8) | (vec[0] as u32))
 }
 
-pub(crate) fn save_u32(data: &mut [u8], base: usize, new_data: u32) {
-    data[base] = (new_data & 0xff) as u8;
-    data[base + 1] = ((new_data >> 8) & 0xff) as u8;
-    data[base + 2] = ((new_data >> 16) & 0xff) as u8;
-    data[base + 3] = ((new_data >> 24) & 0xff) as u8;
+pub(crate) fn save_u32(data: &mut [u8], base: usize, new_data: u32) -> Result<(), ShooterError> {
+    let vec = extract_slice_mut(data, base, 4)?;
+    vec[0] = (new_data & 0xff) as u8;
+    vec[1] = ((new_data >> 8) & 0xff) as u8;
+    vec[2] = ((new_data >> 16) & 0xff) as u8;
+    vec[3] = ((new_data >> 24) & 0xff) as u8;
+    Ok(())
 }
 
-pub(crate) fn save_u16(data: &mut [u8], base: usize, new_data: u16) {
-    data[base] = (new_data & 0xff) as u8;
-    data[base + 1] = ((new_data >> 8) & 0xff) as u8;
+pub(crate) fn save_u16(data: &mut [u8], base: usize, new_data: u16) -> Result<(), ShooterError> {
+    let vec = extract_slice_mut(data, base, 2)?;
+    vec[0] = (new_data & 0xff) as u8;
+    vec[1] = ((new_data >> 8) & 0xff) as u8;
+    Ok(())
 }
 
-pub(crate) fn clear_bit(data: &mut [u8], base: usize, bit_number: u8){
-    data[base] = data[base] & !(1 << bit_number);
+// Posa a 0 un bit. Retorna si abans estava a 1.
+pub(crate) fn clear_bit(data: &mut [u8], base: usize, bit_number: u8) -> Result<bool, ShooterError> {
+    let byte = &mut extract_slice_mut(data, base, 1)?[0];
+    let was_set = *byte & (1 << bit_number) != 0;
+    *byte &= !(1 << bit_number);
+    Ok(was_set)
 }
 
-pub(crate) fn extract_log_u32(data: &[u8], base: usize) -> u32 {
-    1024 << extract_u32(data, base)
+// Posa a 1 un bit. Retorna si abans ja estava a 1.
+pub(crate) fn set_bit(data: &mut [u8], base: usize, bit_number: u8) -> Result<bool, ShooterError> {
+    let byte = &mut extract_slice_mut(data, base, 1)?[0];
+    let was_set = *byte & (1 << bit_number) != 0;
+    *byte |= 1 << bit_number;
+    Ok(was_set)
 }
 
-pub(crate) fn timestamp_to_date_time(timestamp: u32) -> String {
-    let naive_datetime = NaiveDateTime::from_timestamp(timestamp as i64, 0);
-    let time: DateTime<Utc> = DateTime::from_utc(naive_datetime, Utc);
-    time.format("%a %b %e %T %Y").to_string()
+pub(crate) fn test_bit(data: &[u8], base: usize, bit_number: u8) -> Result<bool, ShooterError> {
+    Ok(extract_slice(data, base, 1)?[0] & (1 << bit_number) != 0)
+}
+
+pub(crate) fn extract_log_u32(data: &[u8], base: usize) -> Result<u32, ShooterError> {
+    1024u32.checked_shl(extract_u32(data, base)?).ok_or(ShooterError::OutOfBounds { offset: base, len: 4 })
 }
 
 pub(crate) fn current_time() -> u32 {
     SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards :)").as_secs() as u32
-}
\ No newline at end of file
+}
+
+// Separa un path en els seus components, ignorant '/' repetides i '.'.
+pub(crate) fn split_path(path: &str) -> Vec<&str> {
+    path.split('/').filter(|c| !c.is_empty() && *c != ".").collect()
+}
+
+// Path absolut i sense components buits, per mostrar-lo a l'usuari.
+pub(crate) fn normalize_path(path: &str) -> String {
+    format!("/{}", split_path(path).join("/"))
+}
+
+// Afegeix name al final del directori dir_path.
+pub(crate) fn join_path(dir_path: &str, name: &str) -> String {
+    format!("{}/{}", dir_path.trim_end_matches('/'), name)
+}
+
+// Converteix una data i hora en format DOS (FAT) a timestamp UNIX. None si la data no està definida.
+pub(crate) fn dos_to_timestamp(date: u16, time: u16) -> Option<u32> {
+    if date == 0 {
+        return None;
+    }
+    let year = 1980 + (date >> 9) as i32;
+    let month = ((date >> 5) & 0x0F) as u32;
+    let day = (date & 0x1F) as u32;
+    let hour = (time >> 11) as u32;
+    let minute = ((time >> 5) & 0x3F) as u32;
+    let second = ((time & 0x1F) * 2) as u32;
+
+    let date_time = NaiveDate::from_ymd_opt(year, month, day)?.and_hms_opt(hour, minute, second)?;
+    Some(date_time.and_utc().timestamp() as u32)
+}
+
+// Converteix un timestamp UNIX a (data, hora) en format DOS. Els segons es guarden de 2 en 2.
+pub(crate) fn timestamp_to_dos(timestamp: u32) -> (u16, u16) {
+    let date_time = DateTime::from_timestamp(timestamp as i64, 0).unwrap_or_default();
+    // Les dates DOS només van de 1980 a 2107.
+    let year = (date_time.year() - 1980).clamp(0, 127) as u16;
+    let date = (year << 9) | ((date_time.month() as u16) << 5) | date_time.day() as u16;
+    let time = ((date_time.hour() as u16) << 11) | ((date_time.minute() as u16) << 5) | (date_time.second() as u16 / 2);
+    (date, time)
+}